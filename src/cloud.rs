@@ -0,0 +1,238 @@
+use crate::errors::HWIDError;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Link-local address of the metadata service of AWS, GCP and Azure
+const METADATA_BASE_URL: &str = "http://169.254.169.254";
+const METADATA_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A cloud provider whose metadata service knows the instance ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CloudProvider {
+    /// Amazon EC2, using an IMDSv2 session token
+    AWS,
+    /// Google Compute Engine
+    GCP,
+    /// Azure Virtual Machines, using the `vmId`
+    Azure,
+}
+
+/// Where [`HWIDComponent::CloudInstanceID`](crate::HWIDComponent::CloudInstanceID) asks for the instance ID
+/// when it is not found in local files.
+///
+/// # Examples
+///
+/// ```
+/// use machineid_rs::{CloudMetadata, CloudProvider};
+/// use std::time::Duration;
+///
+/// let metadata = CloudMetadata {
+///     base_url: "http://127.0.0.1:1338".to_string(),
+///     timeout: Duration::from_secs(1),
+///     ..CloudMetadata::new(CloudProvider::AWS)
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CloudMetadata {
+    pub provider: CloudProvider,
+    /// Base URL of the metadata service, only `http://` is supported
+    pub base_url: String,
+    /// Timeout of connecting to the service and of each read and write
+    pub timeout: Duration,
+}
+
+fn metadata_error(message: &str) -> HWIDError {
+    HWIDError::new("CloudMetadataError", message)
+}
+
+impl CloudMetadata {
+    /// The metadata service of `provider` at its usual address, with a 500ms timeout.
+    pub fn new(provider: CloudProvider) -> Self {
+        CloudMetadata {
+            provider,
+            base_url: METADATA_BASE_URL.to_string(),
+            timeout: METADATA_TIMEOUT,
+        }
+    }
+
+    /// Sends a HTTP/1.0 request and returns the body of a `200` response.
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> Result<String, HWIDError> {
+        let url = self
+            .base_url
+            .strip_prefix("http://")
+            .ok_or(metadata_error("Only http:// metadata URLs are supported"))?;
+        let (host, prefix) = match url.find('/') {
+            Some(index) => (&url[..index], url[index..].trim_end_matches('/')),
+            None => (url, ""),
+        };
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or(metadata_error("Could not resolve the metadata service"))?;
+
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut request = format!("{method} {prefix}{path} HTTP/1.0\r\nHost: {host}\r\n");
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("Content-Length: 0\r\n\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        let response = String::from_utf8(response)?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or(metadata_error("Invalid response from the metadata service"))?;
+        let status = head.split(' ').nth(1).unwrap_or_default();
+        if status != "200" {
+            return Err(metadata_error(&format!(
+                "The metadata service answered {method} {path} with status {status}"
+            )));
+        }
+        Ok(body.trim().to_string())
+    }
+
+    pub(crate) fn get_instance_id(&self) -> Result<String, HWIDError> {
+        let id = match self.provider {
+            CloudProvider::AWS => {
+                let token = self.request(
+                    "PUT",
+                    "/latest/api/token",
+                    &[("X-aws-ec2-metadata-token-ttl-seconds", "60")],
+                )?;
+                self.request(
+                    "GET",
+                    "/latest/meta-data/instance-id",
+                    &[("X-aws-ec2-metadata-token", &token)],
+                )?
+            }
+            CloudProvider::GCP => self.request(
+                "GET",
+                "/computeMetadata/v1/instance/id",
+                &[("Metadata-Flavor", "Google")],
+            )?,
+            CloudProvider::Azure => self.request(
+                "GET",
+                "/metadata/instance/compute/vmId?api-version=2021-02-01&format=text",
+                &[("Metadata", "true")],
+            )?,
+        };
+        if id.is_empty() {
+            return Err(metadata_error(
+                "The metadata service returned an empty instance ID",
+            ));
+        }
+        Ok(id)
+    }
+}
+
+/// Reads the instance ID from local files, then from the metadata service if one is given.
+pub(crate) fn get_cloud_instance_id(metadata: Option<&CloudMetadata>) -> Result<String, HWIDError> {
+    #[cfg(target_os = "linux")]
+    if let Ok(id) = crate::linux::get_cloud_instance_id() {
+        return Ok(id);
+    }
+    match metadata {
+        Some(metadata) => metadata.get_instance_id(),
+        None => Err(HWIDError::new(
+            "CloudInstanceID",
+            "Could not find the instance ID in local files",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    /// Answers each connection with the next response, and sends back the requests it got.
+    fn fake_server(responses: Vec<&'static str>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                sender.send(String::from_utf8(request).unwrap()).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (base_url, receiver)
+    }
+
+    fn metadata(provider: CloudProvider, base_url: String) -> CloudMetadata {
+        CloudMetadata {
+            base_url,
+            timeout: Duration::from_secs(5),
+            ..CloudMetadata::new(provider)
+        }
+    }
+
+    #[test]
+    fn aws_instance_id() {
+        let (base_url, requests) = fake_server(vec![
+            "HTTP/1.0 200 OK\r\n\r\nAQAEAGx0b2tlbg==",
+            "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\ni-0a1b2c3d4e5f67890",
+        ]);
+        let id = metadata(CloudProvider::AWS, base_url)
+            .get_instance_id()
+            .unwrap();
+        assert_eq!(id, "i-0a1b2c3d4e5f67890");
+
+        let token_request = requests.recv().unwrap();
+        assert!(token_request.starts_with("PUT /latest/api/token HTTP/1.0\r\n"));
+        assert!(token_request.contains("X-aws-ec2-metadata-token-ttl-seconds: 60\r\n"));
+        let id_request = requests.recv().unwrap();
+        assert!(id_request.starts_with("GET /latest/meta-data/instance-id HTTP/1.0\r\n"));
+        assert!(id_request.contains("X-aws-ec2-metadata-token: AQAEAGx0b2tlbg==\r\n"));
+    }
+
+    #[test]
+    fn gcp_instance_id() {
+        let (base_url, requests) =
+            fake_server(vec!["HTTP/1.0 200 OK\r\n\r\n4520031799277581759\n"]);
+        let id = metadata(CloudProvider::GCP, format!("{base_url}/"))
+            .get_instance_id()
+            .unwrap();
+        assert_eq!(id, "4520031799277581759");
+        let request = requests.recv().unwrap();
+        assert!(request.starts_with("GET /computeMetadata/v1/instance/id HTTP/1.0\r\n"));
+        assert!(request.contains("Metadata-Flavor: Google\r\n"));
+    }
+
+    #[test]
+    fn metadata_errors() {
+        let (base_url, _requests) = fake_server(vec!["HTTP/1.0 404 Not Found\r\n\r\n"]);
+        assert!(metadata(CloudProvider::Azure, base_url)
+            .get_instance_id()
+            .is_err());
+        let https = metadata(CloudProvider::Azure, "https://127.0.0.1".to_string());
+        assert!(https.get_instance_id().is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::errors::HWIDError;
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+const EDID_BLOCK_SIZE: usize = 128;
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+const SERIAL_DESCRIPTOR_TAG: u8 = 0xFF;
+
+/// Identity of a display read from the base block of its EDID.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Edid {
+    pub(crate) manufacturer: String,
+    pub(crate) product_code: u16,
+    pub(crate) serial_number: u32,
+    /// Serial number from the display descriptor, most monitors only fill this one
+    pub(crate) serial_string: Option<String>,
+}
+
+impl Edid {
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, HWIDError> {
+        if data.len() < EDID_BLOCK_SIZE || data[0..8] != EDID_HEADER {
+            return Err(HWIDError::new("EdidError", "Invalid EDID header"));
+        }
+        let block = &data[0..EDID_BLOCK_SIZE];
+        if block.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(HWIDError::new("EdidError", "Invalid EDID checksum"));
+        }
+
+        // Three 5 bit letters, 1 = 'A'
+        let id = u16::from_be_bytes([block[8], block[9]]);
+        let manufacturer = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'A' - 1 + ((id >> shift) & 0x1F) as u8) as char)
+            .collect::<String>();
+
+        let serial_string = DESCRIPTOR_OFFSETS
+            .iter()
+            .map(|offset| &block[*offset..*offset + 18])
+            .find(|d| d[0..3] == [0, 0, 0] && d[3] == SERIAL_DESCRIPTOR_TAG)
+            .map(|d| {
+                let text = &d[5..18];
+                let end = text.iter().position(|b| *b == b'\n').unwrap_or(text.len());
+                String::from_utf8_lossy(&text[..end]).trim().to_string()
+            })
+            .filter(|s| !s.is_empty());
+
+        Ok(Edid {
+            manufacturer,
+            product_code: u16::from_le_bytes([block[10], block[11]]),
+            serial_number: u32::from_le_bytes([block[12], block[13], block[14], block[15]]),
+            serial_string,
+        })
+    }
+
+    pub(crate) fn identifier(&self) -> String {
+        format!(
+            "{}:{:04x}:{:08x}:{}",
+            self.manufacturer,
+            self.product_code,
+            self.serial_number,
+            self.serial_string.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+// EDID base block of a Dell U2719D, with a made-up serial number
+#[cfg(test)]
+pub(crate) const DELL_U2719D: [u8; 128] = [
+    0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x10, 0xac, 0xf1, 0xa0, 0x41, 0x36, 0x4d, 0x4c,
+    0x0c, 0x1e, 0x01, 0x04, 0xb5, 0x3c, 0x22, 0x78, 0x3a, 0xe1, 0x45, 0xa8, 0x55, 0x4d, 0xa3, 0x26,
+    0x0b, 0x50, 0x54, 0xa5, 0x4b, 0x00, 0x71, 0x4f, 0x81, 0x80, 0xa9, 0xc0, 0xd1, 0xc0, 0x01, 0x01,
+    0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x56, 0x5e, 0x00, 0xa0, 0xa0, 0xa0, 0x29, 0x50, 0x30, 0x20,
+    0x35, 0x00, 0x55, 0x50, 0x21, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x00, 0xff, 0x00, 0x37, 0x4d, 0x54,
+    0x30, 0x31, 0x38, 0x36, 0x4c, 0x30, 0x42, 0x35, 0x53, 0x0a, 0x00, 0x00, 0x00, 0xfc, 0x00, 0x44,
+    0x45, 0x4c, 0x4c, 0x20, 0x55, 0x32, 0x37, 0x31, 0x39, 0x44, 0x0a, 0x20, 0x00, 0x00, 0x00, 0xfd,
+    0x00, 0x31, 0x4c, 0x1e, 0x5a, 0x19, 0x00, 0x0a, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x0a,
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_captured_edid() {
+        let edid = Edid::parse(&DELL_U2719D).unwrap();
+        assert_eq!(
+            edid,
+            Edid {
+                manufacturer: "DEL".to_string(),
+                product_code: 0xa0f1,
+                serial_number: 0x4c4d3641,
+                serial_string: Some("7MT0186L0B5S".to_string()),
+            }
+        );
+        assert_eq!(edid.identifier(), "DEL:a0f1:4c4d3641:7MT0186L0B5S");
+    }
+
+    #[test]
+    fn reject_corrupted_edid() {
+        let mut data = DELL_U2719D;
+        data[20] ^= 0x01;
+        assert!(Edid::parse(&data).is_err());
+        assert!(Edid::parse(&DELL_U2719D[0..64]).is_err());
+        assert!(Edid::parse(&[0u8; 128]).is_err());
+    }
+}
@@ -0,0 +1,558 @@
+use crate::errors::HWIDError;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The container or sandbox the process is running in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Container {
+    Docker,
+    Podman,
+    LXC,
+    SystemdNspawn,
+    Kubernetes,
+    Flatpak,
+    Snap,
+    /// Windows Subsystem for Linux, with the name of the distribution when it is known
+    WSL(Option<String>),
+    /// Another container type, as reported by the `container` environment variable
+    Other(String),
+}
+
+/// What [`IdBuilder`](crate::IdBuilder) does with `HWIDComponent::SystemID` inside a container.
+///
+/// Containers often have no `/etc/machine-id`, or one baked into the image and shared
+/// by every container started from it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ContainerPolicy {
+    /// Use the machine id found in the container, as outside of it
+    #[default]
+    Ignore,
+    /// Fail to build the ID
+    Error,
+    /// Use the ID of the container itself, which changes when the container is recreated
+    ContainerScoped,
+    /// Read the host's machine-id from a bind-mount, i.e. `-v /etc/machine-id:/host/machine-id:ro`
+    HostMachineId(PathBuf),
+    /// Use a file token at the path, created on first use
+    ///
+    /// Under WSL the machine-id is generated per distribution and DMI is virtual,
+    /// so a token on the Windows side, i.e. `/mnt/c/ProgramData/<app>/token`, is shared
+    /// by every distribution of the same Windows install.
+    FileToken(PathBuf),
+    /// Pick the most stable source for the container: the host's machine-id mounted at the path
+    /// when it is readable, then the node name in Kubernetes, and the container ID elsewhere
+    Automatic(PathBuf),
+}
+
+/// The hypervisor of the virtual machine the process is running in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hypervisor {
+    KVM,
+    VMware,
+    HyperV,
+    Xen,
+    VirtualBox,
+    /// QEMU without KVM acceleration
+    QEMU,
+    /// Another hypervisor, with the vendor string it reported
+    Other(String),
+}
+
+/// Environment variables the node name is usually exposed as with the downward API
+const NODE_NAME_VARS: [&str; 2] = ["NODE_NAME", "KUBERNETES_NODE_NAME"];
+
+fn read_file(root: &Path, path: &str) -> Option<String> {
+    fs::read_to_string(root.join(path)).ok()
+}
+
+fn container_from_name(name: &str) -> Container {
+    match name.trim() {
+        "docker" => Container::Docker,
+        "podman" => Container::Podman,
+        "lxc" | "lxc-libvirt" => Container::LXC,
+        "systemd-nspawn" => Container::SystemdNspawn,
+        "flatpak" => Container::Flatpak,
+        "wsl" => Container::WSL(None),
+        other => Container::Other(other.to_string()),
+    }
+}
+
+fn detect_container_from(root: &Path, env: &dyn Fn(&str) -> Option<String>) -> Option<Container> {
+    let cgroup = read_file(root, "proc/1/cgroup").unwrap_or_default();
+
+    // Kubernetes pods are also Docker or Podman containers, so they are checked first
+    if env("KUBERNETES_SERVICE_HOST").is_some() || cgroup.contains("kubepods") {
+        return Some(Container::Kubernetes);
+    }
+    if root.join(".flatpak-info").exists() {
+        return Some(Container::Flatpak);
+    }
+    if env("SNAP").is_some() && env("SNAP_NAME").is_some() {
+        return Some(Container::Snap);
+    }
+    if root.join("run/.containerenv").exists() {
+        return Some(Container::Podman);
+    }
+    if root.join(".dockerenv").exists() {
+        return Some(Container::Docker);
+    }
+    // Containers under WSL share its kernel, so this comes after their marker files
+    let kernel = read_file(root, "proc/sys/kernel/osrelease").unwrap_or_default();
+    let distro = env("WSL_DISTRO_NAME").filter(|name| !name.is_empty());
+    if distro.is_some() || kernel.to_lowercase().contains("microsoft") {
+        return Some(Container::WSL(distro));
+    }
+
+    // Set by most container managers for the init process, and by systemd for everyone
+    let container = env("container")
+        .or_else(|| read_file(root, "run/systemd/container"))
+        .or_else(|| {
+            read_file(root, "proc/1/environ")?
+                .split('\0')
+                .find_map(|var| var.strip_prefix("container=").map(|v| v.to_string()))
+        })
+        .filter(|name| !name.trim().is_empty());
+    if let Some(name) = container {
+        return Some(container_from_name(&name));
+    }
+
+    if cgroup.contains("/docker/") || cgroup.contains("docker-") {
+        return Some(Container::Docker);
+    }
+    if cgroup.contains("libpod") {
+        return Some(Container::Podman);
+    }
+    if cgroup.contains("/lxc/") || cgroup.contains("lxc.payload") {
+        return Some(Container::LXC);
+    }
+    None
+}
+
+/// Detects whether the process runs inside a container or sandbox.
+///
+/// # Examples
+///
+/// ```
+/// use machineid_rs::detect_container;
+///
+/// if let Some(container) = detect_container() {
+///     println!("Running inside {:?}", container);
+/// }
+/// ```
+pub fn detect_container() -> Option<Container> {
+    detect_container_from(Path::new("/"), &|name| env::var(name).ok())
+}
+
+/// Reads the hypervisor vendor from CPUID leaf 0x40000000, if the hypervisor bit of leaf 1 is set.
+///
+/// Windows hosts running Hyper-V or VBS also report "Microsoft Hv", but from the root partition,
+/// which runs on the hardware: those have the CreatePartitions privilege (leaf 0x40000003, EBX bit 0)
+/// and return `None`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cpuid_hypervisor_vendor() -> Option<String> {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid;
+
+    // __cpuid is only safe to call since Rust 1.87
+    #[allow(unused_unsafe)]
+    let (features, vendor) = unsafe { (__cpuid(1), __cpuid(0x4000_0000)) };
+    if features.ecx & (1 << 31) == 0 {
+        return None;
+    }
+    let bytes = [vendor.ebx, vendor.ecx, vendor.edx]
+        .iter()
+        .flat_map(|register| register.to_le_bytes())
+        .collect::<Vec<u8>>();
+    let name = String::from_utf8_lossy(&bytes)
+        .trim_end_matches('\0')
+        .to_string();
+    if name == "Microsoft Hv" && vendor.eax >= 0x4000_0003 {
+        #[allow(unused_unsafe)]
+        let features = unsafe { __cpuid(0x4000_0003) };
+        if features.ebx & 1 != 0 {
+            return None;
+        }
+    }
+    Some(name)
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn cpuid_hypervisor_vendor() -> Option<String> {
+    None
+}
+
+fn hypervisor_from_cpuid(vendor: &str) -> Hypervisor {
+    match vendor {
+        "KVMKVMKVM" => Hypervisor::KVM,
+        "VMwareVMware" => Hypervisor::VMware,
+        "Microsoft Hv" => Hypervisor::HyperV,
+        "XenVMMXenVMM" => Hypervisor::Xen,
+        "VBoxVBoxVBox" => Hypervisor::VirtualBox,
+        "TCGTCGTCGTCG" => Hypervisor::QEMU,
+        other => Hypervisor::Other(other.trim().to_string()),
+    }
+}
+
+fn hypervisor_from_dmi(root: &Path) -> Option<Hypervisor> {
+    let dmi = ["sys_vendor", "product_name", "bios_vendor", "board_vendor"]
+        .iter()
+        .filter_map(|field| read_file(root, &format!("sys/class/dmi/id/{field}")))
+        .collect::<Vec<String>>()
+        .join("\n");
+    if dmi.contains("VMware") {
+        Some(Hypervisor::VMware)
+    } else if dmi.contains("VirtualBox") || dmi.contains("innotek GmbH") {
+        Some(Hypervisor::VirtualBox)
+    } else if dmi.contains("Microsoft Corporation") && dmi.contains("Virtual Machine") {
+        Some(Hypervisor::HyperV)
+    } else if dmi.contains("Xen") {
+        Some(Hypervisor::Xen)
+    } else if dmi.contains("KVM")
+        || dmi.contains("Amazon EC2")
+        || dmi.contains("Google Compute Engine")
+    {
+        Some(Hypervisor::KVM)
+    } else if dmi.contains("QEMU") {
+        Some(Hypervisor::QEMU)
+    } else {
+        None
+    }
+}
+
+fn detect_hypervisor_from(root: &Path, cpuid_vendor: Option<String>) -> Option<Hypervisor> {
+    // CPUID tells the accelerator apart, i.e. KVM behind QEMU's DMI strings
+    if let Some(vendor) = cpuid_vendor.filter(|vendor| !vendor.is_empty()) {
+        return Some(hypervisor_from_cpuid(&vendor));
+    }
+    if let Some(hypervisor) = hypervisor_from_dmi(root) {
+        return Some(hypervisor);
+    }
+    match read_file(root, "sys/hypervisor/type")?.trim() {
+        "" => None,
+        "xen" => Some(Hypervisor::Xen),
+        other => Some(Hypervisor::Other(other.to_string())),
+    }
+}
+
+/// Detects whether the process runs inside a virtual machine, and which hypervisor runs it.
+///
+/// Machines cloned from the same image share their machine id and disk UUIDs, so inside
+/// a VM you may want to add a `HWIDComponent::FileToken`, see [`IdBuilder::require_file_token_in_vm`](crate::IdBuilder::require_file_token_in_vm).
+///
+/// # Examples
+///
+/// ```
+/// use machineid_rs::detect_hypervisor;
+///
+/// if let Some(hypervisor) = detect_hypervisor() {
+///     println!("Running inside a {:?} virtual machine", hypervisor);
+/// }
+/// ```
+pub fn detect_hypervisor() -> Option<Hypervisor> {
+    detect_hypervisor_from(Path::new("/"), cpuid_hypervisor_vendor())
+}
+
+/// Finds a 64 hex digits container ID in a path such as `/docker/<id>` or `docker-<id>.scope`.
+fn find_container_id(text: &str) -> Option<String> {
+    text.split(|c: char| !c.is_ascii_hexdigit())
+        .find(|part| part.len() == 64)
+        .map(|id| id.to_lowercase())
+}
+
+fn get_container_id_from(root: &Path) -> Result<String, HWIDError> {
+    if let Some(containerenv) = read_file(root, "run/.containerenv") {
+        let id = containerenv
+            .lines()
+            .find_map(|line| line.strip_prefix("id="))
+            .map(|id| id.trim_matches('"').to_string());
+        if let Some(id) = id.filter(|id| !id.is_empty()) {
+            return Ok(id);
+        }
+    }
+    // With cgroup v2 the cgroup path is hidden, but Docker's own mounts give the ID away
+    let mountinfo = read_file(root, "proc/self/mountinfo").unwrap_or_default();
+    let from_mounts = mountinfo
+        .lines()
+        .filter(|line| line.contains("/containers/"))
+        .find_map(find_container_id);
+    let cgroup = read_file(root, "proc/self/cgroup").unwrap_or_default();
+    from_mounts
+        .or_else(|| cgroup.lines().find_map(find_container_id))
+        .ok_or(HWIDError::new(
+            "ContainerError",
+            "Could not find the ID of the container",
+        ))
+}
+
+pub(crate) fn get_container_id() -> Result<String, HWIDError> {
+    get_container_id_from(Path::new("/"))
+}
+
+pub(crate) fn get_host_machine_id(path: &Path) -> Result<String, HWIDError> {
+    let id = fs::read_to_string(path)?.trim().to_string();
+    if id.is_empty() {
+        return Err(HWIDError::new(
+            "ContainerError",
+            &format!("The host machine-id at {} is empty", path.display()),
+        ));
+    }
+    Ok(id)
+}
+
+fn get_kubernetes_node_name_from(
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, HWIDError> {
+    NODE_NAME_VARS
+        .iter()
+        .filter_map(|name| env(name))
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .ok_or(HWIDError::new(
+            "KubernetesError",
+            "The node name is not set, expose spec.nodeName as NODE_NAME with the downward API",
+        ))
+}
+
+pub(crate) fn get_kubernetes_node_name() -> Result<String, HWIDError> {
+    get_kubernetes_node_name_from(&|name| env::var(name).ok())
+}
+
+/// Finds a pod UID in a cgroup such as `/kubepods/besteffort/pod<uid>/`, `kubepods-pod<uid>.slice`
+/// where the dashes are underscores, or a kubelet path such as `/var/lib/kubelet/pods/<uid>/`.
+fn find_pod_uid(text: &str) -> Option<String> {
+    text.match_indices("pod").find_map(|(index, _)| {
+        let rest = &text[index + 3..];
+        let uid = rest.strip_prefix("s/").unwrap_or(rest).get(..36)?;
+        let uid = uid.replace('_', "-").to_lowercase();
+        let is_uid = uid.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+            && uid.chars().all(|c| c == '-' || c.is_ascii_hexdigit());
+        is_uid.then_some(uid)
+    })
+}
+
+fn get_kubernetes_pod_uid_from(root: &Path) -> Result<String, HWIDError> {
+    let cgroup = read_file(root, "proc/self/cgroup").unwrap_or_default();
+    // With a cgroup namespace the cgroup is `/`, but the kubelet's mounts give the UID away
+    let mountinfo = read_file(root, "proc/self/mountinfo").unwrap_or_default();
+    cgroup
+        .lines()
+        .filter(|line| line.contains("kubepods"))
+        .find_map(find_pod_uid)
+        .or_else(|| {
+            mountinfo
+                .lines()
+                .filter(|line| line.contains("/pods/"))
+                .find_map(find_pod_uid)
+        })
+        .ok_or(HWIDError::new(
+            "KubernetesError",
+            "Could not find the UID of the pod",
+        ))
+}
+
+pub(crate) fn get_kubernetes_pod_uid() -> Result<String, HWIDError> {
+    get_kubernetes_pod_uid_from(Path::new("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::fixture_dir;
+
+    const CONTAINER_ID: &str = "3f4e5d6c7b8a99887766554433221100ffeeddccbbaa00112233445566778899";
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn detect_from_marker_files() {
+        let root = fixture_dir(&[(".dockerenv", "")]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Docker)
+        );
+        let root = fixture_dir(&[("run/.containerenv", "engine=\"podman-4.3.1\"\n")]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Podman)
+        );
+        let root = fixture_dir(&[(".flatpak-info", "[Application]\n")]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Flatpak)
+        );
+        let root = fixture_dir(&[("proc/1/cgroup", "0::/init.scope\n")]);
+        assert_eq!(detect_container_from(&root, &no_env), None);
+    }
+
+    #[test]
+    fn detect_from_environment_and_cgroups() {
+        let root = fixture_dir(&[("proc/1/cgroup", "0::/\n")]);
+        let env = |name: &str| (name == "container").then(|| "systemd-nspawn".to_string());
+        assert_eq!(
+            detect_container_from(&root, &env),
+            Some(Container::SystemdNspawn)
+        );
+        let env = |name: &str| name.starts_with("SNAP").then(|| "/snap/app/12".to_string());
+        assert_eq!(detect_container_from(&root, &env), Some(Container::Snap));
+
+        let root = fixture_dir(&[(
+            "proc/1/cgroup",
+            "12:pids:/kubepods/besteffort/pod1b2c/abcd\n",
+        )]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Kubernetes)
+        );
+        let root = fixture_dir(&[("proc/1/cgroup", "0::/lxc.payload.web/\n")]);
+        assert_eq!(detect_container_from(&root, &no_env), Some(Container::LXC));
+        let root = fixture_dir(&[("run/systemd/container", "rkt\n")]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Other("rkt".to_string()))
+        );
+    }
+
+    #[test]
+    fn container_id() {
+        let mountinfo = format!(
+            "612 590 254:1 /var/lib/docker/containers/{CONTAINER_ID}/hostname /etc/hostname rw - ext4 /dev/vda1 rw\n"
+        );
+        let root = fixture_dir(&[("proc/self/mountinfo", mountinfo.as_str())]);
+        assert_eq!(get_container_id_from(&root).unwrap(), CONTAINER_ID);
+
+        let cgroup = format!("0::/system.slice/docker-{CONTAINER_ID}.scope\n");
+        let root = fixture_dir(&[("proc/self/cgroup", cgroup.as_str())]);
+        assert_eq!(get_container_id_from(&root).unwrap(), CONTAINER_ID);
+
+        let root = fixture_dir(&[("run/.containerenv", "name=\"web\"\nid=\"a1b2c3\"\n")]);
+        assert_eq!(get_container_id_from(&root).unwrap(), "a1b2c3");
+
+        let root = fixture_dir(&[("proc/self/cgroup", "0::/\n")]);
+        assert!(get_container_id_from(&root).is_err());
+    }
+
+    #[test]
+    fn detect_hypervisor_from_cpuid_and_dmi() {
+        let root = fixture_dir(&[
+            ("sys/class/dmi/id/sys_vendor", "QEMU\n"),
+            (
+                "sys/class/dmi/id/product_name",
+                "Standard PC (Q35 + ICH9, 2009)\n",
+            ),
+        ]);
+        assert_eq!(
+            detect_hypervisor_from(&root, Some("KVMKVMKVM".to_string())),
+            Some(Hypervisor::KVM)
+        );
+        assert_eq!(detect_hypervisor_from(&root, None), Some(Hypervisor::QEMU));
+        assert_eq!(
+            detect_hypervisor_from(&root, Some(" lrpepyh  vr".to_string())),
+            Some(Hypervisor::Other("lrpepyh  vr".to_string()))
+        );
+
+        let root = fixture_dir(&[
+            ("sys/class/dmi/id/sys_vendor", "Microsoft Corporation\n"),
+            ("sys/class/dmi/id/product_name", "Virtual Machine\n"),
+        ]);
+        assert_eq!(
+            detect_hypervisor_from(&root, None),
+            Some(Hypervisor::HyperV)
+        );
+        let root = fixture_dir(&[("sys/class/dmi/id/sys_vendor", "innotek GmbH\n")]);
+        assert_eq!(
+            detect_hypervisor_from(&root, None),
+            Some(Hypervisor::VirtualBox)
+        );
+        let root = fixture_dir(&[("sys/hypervisor/type", "xen\n")]);
+        assert_eq!(detect_hypervisor_from(&root, None), Some(Hypervisor::Xen));
+        let root = fixture_dir(&[("sys/class/dmi/id/sys_vendor", "Dell Inc.\n")]);
+        assert_eq!(detect_hypervisor_from(&root, None), None);
+    }
+
+    #[test]
+    fn kubernetes_node_name() {
+        let env = |name: &str| (name == "NODE_NAME").then(|| "worker-3\n".to_string());
+        assert_eq!(get_kubernetes_node_name_from(&env).unwrap(), "worker-3");
+        assert!(get_kubernetes_node_name_from(&no_env).is_err());
+    }
+
+    #[test]
+    fn kubernetes_pod_uid() {
+        const POD_UID: &str = "8d2f4c1a-6b3e-4f7a-9c5d-0e1f2a3b4c5d";
+
+        let cgroup = format!("12:pids:/kubepods/besteffort/pod{POD_UID}/{CONTAINER_ID}\n");
+        let root = fixture_dir(&[("proc/self/cgroup", cgroup.as_str())]);
+        assert_eq!(get_kubernetes_pod_uid_from(&root).unwrap(), POD_UID);
+
+        let cgroup = format!(
+            "0::/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/cri-containerd-{CONTAINER_ID}.scope\n",
+            POD_UID.replace('-', "_")
+        );
+        let root = fixture_dir(&[("proc/self/cgroup", cgroup.as_str())]);
+        assert_eq!(get_kubernetes_pod_uid_from(&root).unwrap(), POD_UID);
+
+        let mountinfo = format!(
+            "1210 1190 254:1 /var/lib/kubelet/pods/{POD_UID}/etc-hosts /etc/hosts rw - ext4 /dev/vda1 rw\n"
+        );
+        let root = fixture_dir(&[
+            ("proc/self/cgroup", "0::/\n"),
+            ("proc/self/mountinfo", mountinfo.as_str()),
+        ]);
+        assert_eq!(get_kubernetes_pod_uid_from(&root).unwrap(), POD_UID);
+
+        let root = fixture_dir(&[("proc/self/cgroup", "0::/\n")]);
+        assert!(get_kubernetes_pod_uid_from(&root).is_err());
+    }
+
+    #[test]
+    fn host_machine_id() {
+        let root = fixture_dir(&[
+            ("host/etc/machine-id", "d5e9a3c1b7f24e6a9c8b1a2d3e4f5a6b\n"),
+            ("empty/machine-id", "\n"),
+        ]);
+        assert_eq!(
+            get_host_machine_id(&root.join("host/etc/machine-id")).unwrap(),
+            "d5e9a3c1b7f24e6a9c8b1a2d3e4f5a6b"
+        );
+        assert!(get_host_machine_id(&root.join("empty/machine-id")).is_err());
+        assert!(get_host_machine_id(&root.join("missing")).is_err());
+    }
+
+    #[test]
+    fn detect_wsl() {
+        let root = fixture_dir(&[(
+            "proc/sys/kernel/osrelease",
+            "5.15.153.1-microsoft-standard-WSL2\n",
+        )]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::WSL(None))
+        );
+        let env = |name: &str| (name == "WSL_DISTRO_NAME").then(|| "Ubuntu-22.04".to_string());
+        assert_eq!(
+            detect_container_from(&root, &env),
+            Some(Container::WSL(Some("Ubuntu-22.04".to_string())))
+        );
+        let root = fixture_dir(&[("proc/sys/kernel/osrelease", "4.4.0-19041-Microsoft\n")]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::WSL(None))
+        );
+        let root = fixture_dir(&[
+            (
+                "proc/sys/kernel/osrelease",
+                "5.15.153.1-microsoft-standard-WSL2\n",
+            ),
+            (".dockerenv", ""),
+        ]);
+        assert_eq!(
+            detect_container_from(&root, &no_env),
+            Some(Container::Docker)
+        );
+    }
+}
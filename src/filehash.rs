@@ -0,0 +1,117 @@
+use crate::errors::HWIDError;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// SHA-256 of a file, read incrementally so that big files are not loaded in memory.
+pub(crate) fn hash_file<P: AsRef<Path>>(path: P) -> Result<String, HWIDError> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes the entries of `dir` sorted by name, recursing into directories.
+///
+/// Each entry adds its path relative to the root, then its size and contents for regular files,
+/// and its target for symbolic links, which are not followed. Other entries like FIFOs, sockets
+/// and device nodes are skipped, as reading them could block or never end.
+fn hash_entries(root: &Path, dir: &Path, hasher: &mut Sha256) -> Result<(), HWIDError> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<fs::DirEntry>, io::Error>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        // The same tree gives the same hash on every platform
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            hasher.update(format!("{name}/\0"));
+            hash_entries(root, &path, hasher)?;
+        } else if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&path)?;
+            hasher.update(format!("{name}@\0{}\0", target.to_string_lossy()));
+        } else if metadata.is_file() {
+            hasher.update(format!("{name}\0"));
+            hasher.update(metadata.len().to_le_bytes());
+            io::copy(&mut File::open(&path)?, hasher)?;
+        }
+    }
+    Ok(())
+}
+
+/// SHA-256 of a directory tree, over the sorted names, sizes and contents of its files.
+///
+/// Special files like FIFOs and sockets are left out of the hash.
+pub(crate) fn hash_directory<P: AsRef<Path>>(dir: P) -> Result<String, HWIDError> {
+    let dir = dir.as_ref();
+    let mut hasher = Sha256::new();
+    hash_entries(dir, dir, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::fixture_dir;
+
+    #[test]
+    fn file_hash() {
+        let root = fixture_dir(&[("abc", "abc")]);
+        assert_eq!(
+            hash_file(root.join("abc")).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let big = vec![0xA5u8; 3 * 1024 * 1024];
+        fs::write(root.join("big"), &big).unwrap();
+        assert_eq!(
+            hash_file(root.join("big")).unwrap(),
+            hex::encode(Sha256::digest(&big))
+        );
+        assert!(hash_file(root.join("missing")).is_err());
+    }
+
+    #[test]
+    fn directory_hash() {
+        let bundle = [
+            ("assets/models/a.bin", "model a"),
+            ("assets/models/b.bin", "model b"),
+            ("assets/config.toml", "level = 3\n"),
+        ];
+        let reversed = bundle.iter().rev().cloned().collect::<Vec<_>>();
+        let hash = hash_directory(fixture_dir(&bundle).join("assets")).unwrap();
+        assert_eq!(
+            hash_directory(fixture_dir(&reversed).join("assets")).unwrap(),
+            hash
+        );
+
+        let changed = fixture_dir(&bundle);
+        fs::write(changed.join("assets/models/b.bin"), "model c").unwrap();
+        assert_ne!(hash_directory(changed.join("assets")).unwrap(), hash);
+
+        let renamed = fixture_dir(&bundle);
+        fs::rename(
+            renamed.join("assets/models/b.bin"),
+            renamed.join("assets/models/c.bin"),
+        )
+        .unwrap();
+        assert_ne!(hash_directory(renamed.join("assets")).unwrap(), hash);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_special_files() {
+        let bundle = [("assets/config.toml", "level = 3\n")];
+        let hash = hash_directory(fixture_dir(&bundle).join("assets")).unwrap();
+
+        // Opening a FIFO blocks until a writer shows up
+        let fifo = fixture_dir(&bundle);
+        let path = std::ffi::CString::new(fifo.join("assets/control").to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+        assert_eq!(hash_directory(fifo.join("assets")).unwrap(), hash);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::errors::HWIDError;
+use std::io::{Read, Seek, SeekFrom};
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const SECTOR_SIZES: [u64; 2] = [512, 4096];
+const MIN_HEADER_SIZE: usize = 92;
+const MIN_ENTRY_SIZE: usize = 128;
+const MAX_ENTRY_SIZE: usize = 4096;
+const MAX_ENTRIES: u32 = 1024;
+pub(crate) const ESP_TYPE_GUID: &str = "c12a7328-f81f-11d2-ba4b-00a0c93ec93b";
+
+/// A used entry of the GPT partition table.
+pub(crate) struct GptPartition {
+    /// Partition number, as in `/dev/sda1`
+    pub(crate) number: u32,
+    pub(crate) type_guid: String,
+    /// The PARTUUID
+    pub(crate) unique_guid: String,
+}
+
+pub(crate) struct Gpt {
+    pub(crate) disk_guid: String,
+    pub(crate) partitions: Vec<GptPartition>,
+}
+
+/// Formats an on-disk GUID, whose first three fields are little-endian.
+fn format_guid(bytes: &[u8]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        u16::from_le_bytes([bytes[6], bytes[7]]),
+        hex::encode(&bytes[8..10]),
+        hex::encode(&bytes[10..16])
+    )
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    (read_u32(bytes, offset + 4) as u64) << 32 | read_u32(bytes, offset) as u64
+}
+
+fn invalid(message: &str) -> HWIDError {
+    HWIDError::new("GptError", message)
+}
+
+impl Gpt {
+    /// Reads the primary GPT of a disk, checking the CRC of the header and of the partition entries.
+    pub(crate) fn read<R: Read + Seek>(disk: &mut R) -> Result<Self, HWIDError> {
+        let mut error = invalid("Could not find a GPT header");
+        for sector_size in SECTOR_SIZES {
+            match Self::read_with_sector_size(disk, sector_size) {
+                Ok(gpt) => return Ok(gpt),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    fn read_with_sector_size<R: Read + Seek>(
+        disk: &mut R,
+        sector_size: u64,
+    ) -> Result<Self, HWIDError> {
+        let mut header = vec![0; sector_size as usize];
+        disk.seek(SeekFrom::Start(sector_size))?;
+        disk.read_exact(&mut header)?;
+        if &header[0..8] != GPT_SIGNATURE {
+            return Err(invalid("Could not find a GPT header"));
+        }
+
+        let header_size = read_u32(&header, 12) as usize;
+        if header_size < MIN_HEADER_SIZE || header_size > header.len() {
+            return Err(invalid("Invalid GPT header size"));
+        }
+        let expected_crc = read_u32(&header, 16);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header[0..16]);
+        hasher.update(&[0; 4]);
+        hasher.update(&header[20..header_size]);
+        if hasher.finalize() != expected_crc {
+            return Err(invalid("Invalid GPT header CRC"));
+        }
+
+        let entries_lba = read_u64(&header, 72);
+        let entry_count = read_u32(&header, 80);
+        let entry_size = read_u32(&header, 84) as usize;
+        // The spec requires entries of 128 * 2^n bytes
+        if entry_count > MAX_ENTRIES
+            || !(MIN_ENTRY_SIZE..=MAX_ENTRY_SIZE).contains(&entry_size)
+            || !entry_size.is_multiple_of(MIN_ENTRY_SIZE)
+            || !(entry_size / MIN_ENTRY_SIZE).is_power_of_two()
+        {
+            return Err(invalid("Invalid GPT partition entries"));
+        }
+        let entries_offset = entries_lba
+            .checked_mul(sector_size)
+            .ok_or(invalid("Invalid GPT partition entries location"))?;
+        let mut entries = vec![0; entry_count as usize * entry_size];
+        disk.seek(SeekFrom::Start(entries_offset))?;
+        disk.read_exact(&mut entries)?;
+        if crc32fast::hash(&entries) != read_u32(&header, 88) {
+            return Err(invalid("Invalid GPT partition entries CRC"));
+        }
+
+        let partitions = entries
+            .chunks(entry_size)
+            .zip(1..)
+            .filter(|(entry, _)| entry[0..16].iter().any(|b| *b != 0))
+            .map(|(entry, number)| GptPartition {
+                number,
+                type_guid: format_guid(&entry[0..16]),
+                unique_guid: format_guid(&entry[16..32]),
+            })
+            .collect();
+
+        Ok(Gpt {
+            disk_guid: format_guid(&header[56..72]),
+            partitions,
+        })
+    }
+
+    pub(crate) fn partition(&self, number: u32) -> Option<&GptPartition> {
+        self.partitions.iter().find(|p| p.number == number)
+    }
+
+    pub(crate) fn esp(&self) -> Option<&GptPartition> {
+        self.partitions
+            .iter()
+            .find(|p| p.type_guid == ESP_TYPE_GUID)
+    }
+}
+
+/// Parses a GUID in its textual form into its on-disk layout.
+#[cfg(test)]
+fn encode_guid(guid: &str) -> Vec<u8> {
+    let bytes = hex::decode(guid.replace('-', "")).unwrap();
+    let mut encoded = vec![];
+    encoded.extend(bytes[0..4].iter().rev());
+    encoded.extend(bytes[4..6].iter().rev());
+    encoded.extend(bytes[6..8].iter().rev());
+    encoded.extend(&bytes[8..16]);
+    encoded
+}
+
+/// Builds a disk image with a GPT holding the given `(type, PARTUUID)` partitions.
+#[cfg(test)]
+pub(crate) fn gpt_image(
+    sector_size: usize,
+    disk_guid: &str,
+    partitions: &[(&str, &str)],
+) -> Vec<u8> {
+    let entry_count = 128;
+    let entries_lba = 2;
+    let mut image = vec![0u8; sector_size * (entries_lba + 32 + 1)];
+
+    let entries_start = entries_lba * sector_size;
+    for (index, (type_guid, unique_guid)) in partitions.iter().enumerate() {
+        let entry = entries_start + index * 128;
+        image[entry..entry + 16].copy_from_slice(&encode_guid(type_guid));
+        image[entry + 16..entry + 32].copy_from_slice(&encode_guid(unique_guid));
+    }
+    let entries_crc = crc32fast::hash(&image[entries_start..entries_start + entry_count * 128]);
+
+    let header = sector_size;
+    image[header..header + 8].copy_from_slice(GPT_SIGNATURE);
+    image[header + 8..header + 12].copy_from_slice(&[0, 0, 1, 0]);
+    image[header + 12..header + 16].copy_from_slice(&92u32.to_le_bytes());
+    image[header + 24..header + 32].copy_from_slice(&1u64.to_le_bytes());
+    image[header + 56..header + 72].copy_from_slice(&encode_guid(disk_guid));
+    image[header + 72..header + 80].copy_from_slice(&(entries_lba as u64).to_le_bytes());
+    image[header + 80..header + 84].copy_from_slice(&(entry_count as u32).to_le_bytes());
+    image[header + 84..header + 88].copy_from_slice(&128u32.to_le_bytes());
+    image[header + 88..header + 92].copy_from_slice(&entries_crc.to_le_bytes());
+    let header_crc = crc32fast::hash(&image[header..header + 92]);
+    image[header + 16..header + 20].copy_from_slice(&header_crc.to_le_bytes());
+    image
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const DISK_GUID: &str = "5b3e2a9c-1f4d-4e8b-a2c6-9d0e7f1a3b5c";
+    const ESP_GUID: &str = "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d";
+    const ROOT_GUID: &str = "f0e1d2c3-b4a5-4968-8776-655443322110";
+    const LINUX_TYPE_GUID: &str = "0fc63daf-8483-4772-8e79-3d69d8477de4";
+
+    #[test]
+    fn read_gpt() {
+        for sector_size in [512, 4096] {
+            let image = gpt_image(
+                sector_size,
+                DISK_GUID,
+                &[(ESP_TYPE_GUID, ESP_GUID), (LINUX_TYPE_GUID, ROOT_GUID)],
+            );
+            let gpt = Gpt::read(&mut Cursor::new(image)).unwrap();
+            assert_eq!(gpt.disk_guid, DISK_GUID);
+            assert_eq!(gpt.partitions.len(), 2);
+            assert_eq!(gpt.esp().unwrap().unique_guid, ESP_GUID);
+            assert_eq!(gpt.partition(2).unwrap().unique_guid, ROOT_GUID);
+            assert_eq!(gpt.partition(2).unwrap().type_guid, LINUX_TYPE_GUID);
+            assert!(gpt.partition(3).is_none());
+        }
+    }
+
+    #[test]
+    fn reject_bad_crc() {
+        let mut image = gpt_image(512, DISK_GUID, &[(LINUX_TYPE_GUID, ROOT_GUID)]);
+        image[512 + 60] ^= 0xFF;
+        assert!(Gpt::read(&mut Cursor::new(image)).is_err());
+
+        let mut image = gpt_image(512, DISK_GUID, &[(LINUX_TYPE_GUID, ROOT_GUID)]);
+        image[1024 + 20] ^= 0xFF;
+        assert!(Gpt::read(&mut Cursor::new(image)).is_err());
+    }
+
+    #[test]
+    fn reject_mbr_disk() {
+        assert!(Gpt::read(&mut Cursor::new(vec![0u8; 8192])).is_err());
+    }
+
+    /// Overwrites a header field and fixes the header CRC, as a crafted disk would.
+    fn with_header_field(mut image: Vec<u8>, offset: usize, value: &[u8]) -> Vec<u8> {
+        image[512 + offset..512 + offset + value.len()].copy_from_slice(value);
+        image[512 + 16..512 + 20].copy_from_slice(&[0; 4]);
+        let crc = crc32fast::hash(&image[512..512 + 92]);
+        image[512 + 16..512 + 20].copy_from_slice(&crc.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn reject_malformed_header() {
+        let image = gpt_image(512, DISK_GUID, &[(LINUX_TYPE_GUID, ROOT_GUID)]);
+        let malformed = [
+            // Entries too big, or not 128 bytes times a power of two
+            (84, 0x1000_0000u32.to_le_bytes().to_vec()),
+            (84, 8192u32.to_le_bytes().to_vec()),
+            (84, 200u32.to_le_bytes().to_vec()),
+            (84, 384u32.to_le_bytes().to_vec()),
+            // Entries located past the end of any disk
+            (72, u64::MAX.to_le_bytes().to_vec()),
+            (80, 0x10_0000u32.to_le_bytes().to_vec()),
+        ];
+        for (offset, value) in malformed {
+            let image = with_header_field(image.clone(), offset, &value);
+            assert!(Gpt::read(&mut Cursor::new(image)).is_err());
+        }
+    }
+}
@@ -16,15 +16,42 @@
 
 #![allow(non_snake_case)]
 
+mod cloud;
+#[cfg(target_os = "linux")]
+mod edid;
+mod environment;
 mod errors;
+mod filehash;
+#[cfg(target_os = "linux")]
+mod gpt;
 mod linux;
 mod macos;
+mod report;
+mod ssh;
+#[cfg(target_os = "linux")]
+mod superblock;
+mod token;
 mod utils;
 mod windows;
 
+use cloud::get_cloud_instance_id;
+pub use cloud::{CloudMetadata, CloudProvider};
+pub use environment::{
+    detect_container, detect_hypervisor, Container, ContainerPolicy, Hypervisor,
+};
+use environment::{
+    get_container_id, get_host_machine_id, get_kubernetes_node_name, get_kubernetes_pod_uid,
+};
 use errors::HWIDError;
+use filehash::{hash_directory, hash_file};
 #[cfg(target_os = "linux")]
-use linux::{get_disk_id, get_hwid, get_mac_address};
+use linux::{
+    get_board_compatible, get_board_model, get_boot_mode, get_cpu_topology, get_cpuinfo_serial,
+    get_disk_guid, get_disk_id, get_disk_id_at, get_esp_partuuid, get_hostid, get_hostname,
+    get_hwid, get_installed_memory, get_loader_partuuid, get_mac_address, get_monitor_edid,
+    get_os_release, get_pci_devices, get_root_partuuid, get_secure_boot, get_setup_mode,
+    get_soc_serial, get_usb_volume_id,
+};
 #[cfg(target_os = "macos")]
 use macos::{get_disk_id, get_hwid, get_mac_address};
 #[cfg(target_os = "windows")]
@@ -32,10 +59,77 @@ use windows::{get_disk_id, get_hwid, get_mac_address};
 
 use hmac::{Hmac, Mac};
 use md5::Md5;
+use report::{detect_clone_signals, detect_tamper_signals};
+pub use report::{CloneSignal, HWIDReport, TamperSignal};
 use sha1::Sha1;
 use sha2::Sha256;
+use ssh::get_host_key_fingerprint;
 use sysinfo::{CpuExt, System, SystemExt};
-use utils::file_token;
+use token::{get_stored_token, get_stored_token_with, open_token, seal_token, token_uuid};
+pub use token::{TokenLocation, TokenMode};
+use utils::{architecture, file_token, file_token_with, PRIVATE_TOKEN_MODE};
+
+use std::path::PathBuf;
+
+/// A mounted volume, identified either by where it is mounted or by its filesystem label.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum Volume {
+    /// The path the volume is mounted at, i.e. `/media/usb`
+    MountPoint(PathBuf),
+    /// The filesystem label of the volume
+    Label(String),
+}
+
+/// Where [`HWIDComponent::InstalledMemory`] reads the memory size from.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum MemorySource {
+    /// `MemTotal` of `/proc/meminfo`, readable by every user, which is a bit less than the
+    /// installed memory as it excludes what the firmware and kernel reserve
+    MemTotal,
+    /// The sum of the SMBIOS memory devices, which is only readable by root
+    SMBIOS,
+}
+
+/// How much of the OS release is used by [`HWIDComponent::OSRelease`].
+///
+/// The examples are for Ubuntu 22.04.3 LTS.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum OSGranularity {
+    /// The distribution family from `ID_LIKE`, i.e. `debian`
+    Family,
+    /// The distribution `ID`, i.e. `ubuntu`
+    ID,
+    /// The `ID` and the major `VERSION_ID`, i.e. `ubuntu 22`
+    MajorVersion,
+    /// The `PRETTY_NAME`, i.e. `Ubuntu 22.04.3 LTS`
+    Full,
+}
+
+/// Where [`HWIDComponent::Hostname`] reads the hostname from.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum HostnameSource {
+    /// The configured hostname from `/etc/hostname`
+    Static,
+    /// The pretty hostname from `/etc/machine-info`, i.e. `Alice's Laptop`
+    Pretty,
+    /// The kernel nodename, as returned by `uname -n`, which DHCP may change
+    Kernel,
+}
+
+/// Options of [`HWIDComponent::Hostname`].
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct HostnameOptions {
+    pub source: HostnameSource,
+    /// Converts the hostname to lowercase
+    pub lowercase: bool,
+    /// Removes the domain, `host.example.com` becomes `host`, ignored for the free-form `Pretty` hostname
+    pub strip_domain: bool,
+}
 
 /// The components that can be used to build the HWID.
 
@@ -55,20 +149,141 @@ pub enum HWIDComponent {
     MacAddress,
     /// CPU Vendor ID
     CPUID,
-    /// The contents of a file
-    FileToken(&'static str),
+    /// The contents of a file, created with a random UUID if it is missing or empty
+    ///
+    /// Binary files and files over 64 KiB are replaced by their SHA-256.
+    FileToken(PathBuf),
+    /// A token kept in a standard location, see [`TokenLocation`] and [`TokenMode`]
+    StoredToken(TokenLocation, TokenMode),
+    /// SHA-256 of a file, read incrementally
+    FileHash(PathBuf),
+    /// SHA-256 of a directory tree, over its sorted file names, sizes and contents, i.e. an installed asset bundle
+    ///
+    /// FIFOs, sockets and device nodes in the tree are skipped.
+    DirectoryHash(PathBuf),
     /// UUID of the root disk
     DriveSerial,
+    /// SHA-256 fingerprint of the SSH host key, preferring ed25519, then ecdsa, rsa and dsa keys
+    SSHHostKey,
+    /// Version of the running kernel
+    KernelVersion,
+    /// Architecture of the machine, i.e. `x86_64` or `aarch64`
+    Architecture,
+    /// ID of the cloud instance, which stays the same when the VM image is reused
+    ///
+    /// It is read from EC2's `/sys/hypervisor/uuid` and DMI board asset tag, and cloud-init's instance-id
+    /// on Linux, then from the metadata service when one is given.
+    CloudInstanceID(Option<CloudMetadata>),
+    /// Name of the Kubernetes node, from the `NODE_NAME` or `KUBERNETES_NODE_NAME` environment variable
+    ///
+    /// Expose it in the pod spec with the downward API:
+    /// `env: [{name: NODE_NAME, valueFrom: {fieldRef: {fieldPath: spec.nodeName}}}]`.
+    KubernetesNodeName,
+    /// UID of the Kubernetes pod, read from its cgroup, which changes when the pod is recreated
+    KubernetesPodUID,
+    /// The host's machine-id, mounted into a container at the given path, i.e. a `hostPath` volume of `/etc/machine-id`
+    HostMachineID(PathBuf),
+    /// Number of CPU sockets (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUSockets,
+    /// Number of physical CPU cores, read from sysfs (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUPhysicalCores,
+    /// Number of logical CPU threads (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUThreads,
+    /// Total size of the L2 caches in KiB (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUL2Cache,
+    /// Total size of the L3 caches in KiB (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUL3Cache,
+    /// Installed memory in MiB from the given source, rounded up to the given granularity in MiB (Linux only)
+    ///
+    /// For example `InstalledMemory(MemorySource::MemTotal, 1024)` rounds to whole GiB.
+    #[cfg(target_os = "linux")]
+    InstalledMemory(MemorySource, u64),
+    /// SoC serial number from `/proc/device-tree/serial-number` (Linux only)
+    #[cfg(target_os = "linux")]
+    SoCSerial,
+    /// The `Serial` line of `/proc/cpuinfo`, as found on Raspberry Pis (Linux only)
+    #[cfg(target_os = "linux")]
+    CPUInfoSerial,
+    /// Board model from the device-tree (Linux only)
+    #[cfg(target_os = "linux")]
+    BoardModel,
+    /// Board compatible strings from the device-tree (Linux only)
+    #[cfg(target_os = "linux")]
+    BoardCompatible,
+    /// Hash of the non-hotplug PCI devices, skipping the given PCI base classes (Linux only)
+    ///
+    /// For example `PCIDevices(vec![0x02, 0x0c])` ignores network and serial bus controllers.
+    #[cfg(target_os = "linux")]
+    PCIDevices(Vec<u8>),
+    /// Manufacturer, product code and serial number of the connected displays, read from their EDID (Linux only)
+    #[cfg(target_os = "linux")]
+    MonitorEDID,
+    /// USB vendor, product and serial of the removable device holding a volume, plus its filesystem UUID (Linux only)
+    ///
+    /// Useful to bind a license to a USB stick.
+    #[cfg(target_os = "linux")]
+    USBVolume(Volume),
+    /// Like `DriveSerial`, but for the disk holding the given path instead of the root disk (Linux only)
+    #[cfg(target_os = "linux")]
+    DriveSerialAt(PathBuf),
+    /// GPT disk GUID of the root disk, which survives reformatting (Linux only, needs read access to the disk)
+    #[cfg(target_os = "linux")]
+    DiskGUID,
+    /// PARTUUID of the root partition (Linux only, needs read access to the disk)
+    #[cfg(target_os = "linux")]
+    RootPartUUID,
+    /// PARTUUID of the EFI System Partition of the root disk, read from its GPT (Linux only, needs read access to the disk)
+    #[cfg(target_os = "linux")]
+    ESPPartUUID,
+    /// PARTUUID of the partition the boot loader was started from, from the `LoaderDevicePartUUID`
+    /// EFI variable set by systemd-boot and others (Linux only)
+    ///
+    /// It needs no special permission, but may be another ESP than the root disk's.
+    #[cfg(target_os = "linux")]
+    LoaderPartUUID,
+    /// Whether the machine booted in `uefi` or `bios` mode (Linux only)
+    #[cfg(target_os = "linux")]
+    BootMode,
+    /// Value of the `SecureBoot` EFI variable, `1` or `0` (Linux only)
+    #[cfg(target_os = "linux")]
+    SecureBoot,
+    /// Value of the `SetupMode` EFI variable, `1` or `0` (Linux only)
+    #[cfg(target_os = "linux")]
+    SetupMode,
+    /// The POSIX hostid, from `/etc/hostid` or computed like glibc's `gethostid` (Linux only)
+    #[cfg(target_os = "linux")]
+    HostID,
+    /// The OS from `/etc/os-release`, which unlike `OSName` can ignore point releases (Linux only)
+    #[cfg(target_os = "linux")]
+    OSRelease(OSGranularity),
+    /// The hostname from a chosen source, unlike `MachineName` which may be a transient name set by DHCP (Linux only)
+    #[cfg(target_os = "linux")]
+    Hostname(HostnameOptions),
 }
 
 impl HWIDComponent {
+    fn is_token(&self) -> bool {
+        matches!(
+            self,
+            HWIDComponent::FileToken(_) | HWIDComponent::StoredToken(..)
+        )
+    }
+
     fn to_string(&self) -> Result<String, HWIDError> {
         use HWIDComponent::*;
-        return match self {
+        match self {
             SystemID => get_hwid(),
             CPUCores => {
                 let sys = System::new_all();
-                let cores = sys.physical_core_count().unwrap_or(2);
+                let cores = sys.physical_core_count().ok_or(HWIDError::new(
+                    "CPUCores",
+                    "Could not retrieve the number of physical cores",
+                ))?;
                 Ok(cores.to_string())
             }
             OSName => {
@@ -92,9 +307,86 @@ impl HWIDComponent {
                 let processor = sys.global_cpu_info();
                 Ok(processor.vendor_id().to_string())
             }
-            FileToken(filename) => file_token(filename),
+            FileToken(filename) => file_token(filename).and_then(|token| token_uuid(&token)),
+            StoredToken(location, mode) => get_stored_token(location, mode),
+            FileHash(path) => hash_file(path),
+            DirectoryHash(path) => hash_directory(path),
             DriveSerial => get_disk_id(),
-        };
+            SSHHostKey => get_host_key_fingerprint(),
+            KernelVersion => {
+                let sys = System::new();
+                let version = sys.kernel_version().ok_or(HWIDError::new(
+                    "KernelVersion",
+                    "Could not retrieve Kernel Version",
+                ))?;
+                Ok(version)
+            }
+            Architecture => architecture(),
+            CloudInstanceID(metadata) => get_cloud_instance_id(metadata.as_ref()),
+            KubernetesNodeName => get_kubernetes_node_name(),
+            KubernetesPodUID => get_kubernetes_pod_uid(),
+            HostMachineID(path) => get_host_machine_id(path),
+            #[cfg(target_os = "linux")]
+            CPUSockets => Ok(get_cpu_topology()?.sockets.to_string()),
+            #[cfg(target_os = "linux")]
+            CPUPhysicalCores => Ok(get_cpu_topology()?.cores.to_string()),
+            #[cfg(target_os = "linux")]
+            CPUThreads => Ok(get_cpu_topology()?.threads.to_string()),
+            #[cfg(target_os = "linux")]
+            CPUL2Cache => match get_cpu_topology()?.l2_cache_kb {
+                0 => Err(HWIDError::new(
+                    "CPUL2Cache",
+                    "Could not retrieve L2 cache size",
+                )),
+                size => Ok(size.to_string()),
+            },
+            #[cfg(target_os = "linux")]
+            CPUL3Cache => match get_cpu_topology()?.l3_cache_kb {
+                0 => Err(HWIDError::new(
+                    "CPUL3Cache",
+                    "Could not retrieve L3 cache size",
+                )),
+                size => Ok(size.to_string()),
+            },
+            #[cfg(target_os = "linux")]
+            InstalledMemory(source, granularity) => get_installed_memory(source, *granularity),
+            #[cfg(target_os = "linux")]
+            SoCSerial => get_soc_serial(),
+            #[cfg(target_os = "linux")]
+            CPUInfoSerial => get_cpuinfo_serial(),
+            #[cfg(target_os = "linux")]
+            BoardModel => get_board_model(),
+            #[cfg(target_os = "linux")]
+            BoardCompatible => get_board_compatible(),
+            #[cfg(target_os = "linux")]
+            PCIDevices(excluded_classes) => get_pci_devices(excluded_classes),
+            #[cfg(target_os = "linux")]
+            MonitorEDID => get_monitor_edid(),
+            #[cfg(target_os = "linux")]
+            USBVolume(volume) => get_usb_volume_id(volume),
+            #[cfg(target_os = "linux")]
+            DriveSerialAt(path) => get_disk_id_at(path),
+            #[cfg(target_os = "linux")]
+            DiskGUID => get_disk_guid(),
+            #[cfg(target_os = "linux")]
+            RootPartUUID => get_root_partuuid(),
+            #[cfg(target_os = "linux")]
+            ESPPartUUID => get_esp_partuuid(),
+            #[cfg(target_os = "linux")]
+            LoaderPartUUID => get_loader_partuuid(),
+            #[cfg(target_os = "linux")]
+            BootMode => get_boot_mode(),
+            #[cfg(target_os = "linux")]
+            SecureBoot => get_secure_boot(),
+            #[cfg(target_os = "linux")]
+            SetupMode => get_setup_mode(),
+            #[cfg(target_os = "linux")]
+            HostID => get_hostid(),
+            #[cfg(target_os = "linux")]
+            OSRelease(granularity) => get_os_release(granularity),
+            #[cfg(target_os = "linux")]
+            Hostname(options) => get_hostname(options),
+        }
     }
 }
 
@@ -138,6 +430,10 @@ impl Encryption {
 pub struct IdBuilder {
     parts: Vec<HWIDComponent>,
     pub hash: Encryption,
+    container_policy: ContainerPolicy,
+    require_file_token_in_vm: bool,
+    known_machine_ids: Vec<String>,
+    token_bindings: Vec<HWIDComponent>,
 }
 
 impl IdBuilder {
@@ -161,15 +457,237 @@ impl IdBuilder {
     /// let key = builder.build("mykey").unwrap();
     /// ```
     pub fn build(&mut self, key: &str) -> Result<String, HWIDError> {
-        if self.parts.len() == 0 {
+        let (id, _) = self.build_with_token_signals(key)?;
+        Ok(id)
+    }
+
+    fn build_with_token_signals(&self, key: &str) -> Result<(String, Vec<CloneSignal>), HWIDError> {
+        if self.parts.is_empty() {
             panic!("You must add at least one element to make a machine id");
         }
+        if self.require_file_token_in_vm && !self.uses_file_token(detect_container().as_ref()) {
+            if let Some(hypervisor) = detect_hypervisor() {
+                return Err(HWIDError::new(
+                    "VirtualMachineError",
+                    &format!(
+                        "A FileToken component is required inside a {hypervisor:?} virtual machine"
+                    ),
+                ));
+            }
+        }
+        let binding = self.token_binding()?;
+        let mut signals = vec![];
         let final_string = self
             .parts
             .iter()
-            .map(|p| p.to_string())
+            .map(|p| match (p, &binding) {
+                (HWIDComponent::SystemID, _) => self.system_id(),
+                (_, Some(binding)) if p.is_token() => {
+                    let new_token = || seal_token(key, binding);
+                    let (path, content) = match p {
+                        HWIDComponent::StoredToken(location, mode) => {
+                            get_stored_token_with(location, mode, &new_token)?
+                        }
+                        HWIDComponent::FileToken(path) => (
+                            path.clone(),
+                            file_token_with(path, PRIVATE_TOKEN_MODE, &new_token)?,
+                        ),
+                        _ => unreachable!(),
+                    };
+                    let (token, moved) = open_token(&content, key, binding)?;
+                    if moved {
+                        signals.push(CloneSignal::FileTokenMoved(path));
+                    }
+                    Ok(token)
+                }
+                _ => p.to_string(),
+            })
             .collect::<Result<String, HWIDError>>()?;
-        self.hash.generate_hash(key.as_bytes(), final_string)
+        let id = self.hash.generate_hash(key.as_bytes(), final_string)?;
+        Ok((id, signals))
+    }
+
+    /// The strings of the components tokens are bound to, if there are any tokens to bind.
+    fn token_binding(&self) -> Result<Option<String>, HWIDError> {
+        if self.token_bindings.is_empty() || !self.parts.iter().any(HWIDComponent::is_token) {
+            return Ok(None);
+        }
+        self.token_bindings
+            .iter()
+            .map(|p| match p {
+                _ if p.is_token() => Err(HWIDError::new(
+                    "TokenError",
+                    "A token can't be bound to another token",
+                )),
+                HWIDComponent::SystemID => self.system_id(),
+                _ => p.to_string(),
+            })
+            .collect::<Result<String, HWIDError>>()
+            .map(Some)
+    }
+
+    /// Same as [`IdBuilder::build`], but also returns hints that the machine was cloned from another one,
+    /// or that its identity sources were spoofed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Err`] if there is an error while retrieving the component's strings.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use machineid_rs::{IdBuilder, Encryption, HWIDComponent};
+    ///
+    /// let mut builder = IdBuilder::new(Encryption::MD5);
+    ///
+    /// builder.add_component(HWIDComponent::CPUCores);
+    ///
+    /// let report = builder.build_with_report("mykey").unwrap();
+    /// if !report.clone_signals.is_empty() {
+    ///     println!("{} may be a cloned machine: {:?}", report.id, report.clone_signals);
+    /// }
+    /// ```
+    pub fn build_with_report(&mut self, key: &str) -> Result<HWIDReport, HWIDError> {
+        let (id, token_signals) = self.build_with_token_signals(key)?;
+        let machine_id = get_hwid().ok();
+        let mut clone_signals =
+            detect_clone_signals(machine_id.as_deref(), &self.known_machine_ids);
+        clone_signals.extend(token_signals);
+        Ok(HWIDReport {
+            id,
+            clone_signals,
+            tamper_signals: detect_tamper_signals(),
+        })
+    }
+
+    /// Whether the ID holds a token file, either as a component or as the System ID
+    /// of a `ContainerPolicy::FileToken` inside `container`, i.e. under WSL.
+    fn uses_file_token(&self, container: Option<&Container>) -> bool {
+        self.parts.iter().any(HWIDComponent::is_token)
+            || (container.is_some()
+                && matches!(self.container_policy, ContainerPolicy::FileToken(_))
+                && self
+                    .parts
+                    .iter()
+                    .any(|part| matches!(part, HWIDComponent::SystemID)))
+    }
+
+    fn system_id(&self) -> Result<String, HWIDError> {
+        if self.container_policy == ContainerPolicy::Ignore {
+            return get_hwid();
+        }
+        let container = match detect_container() {
+            Some(container) => container,
+            None => return get_hwid(),
+        };
+        match &self.container_policy {
+            ContainerPolicy::Ignore => get_hwid(),
+            ContainerPolicy::Error => Err(HWIDError::new(
+                "ContainerError",
+                &format!("The System ID is not reliable inside a {container:?} container"),
+            )),
+            ContainerPolicy::ContainerScoped => get_container_id(),
+            ContainerPolicy::HostMachineId(path) => get_host_machine_id(path),
+            ContainerPolicy::FileToken(path) => {
+                file_token(path).and_then(|token| token_uuid(&token))
+            }
+            ContainerPolicy::Automatic(path) => match get_host_machine_id(path) {
+                Ok(id) => Ok(id),
+                Err(_) if container == Container::Kubernetes => get_kubernetes_node_name(),
+                Err(_) => get_container_id(),
+            },
+        }
+    }
+
+    /// Sets what happens to `HWIDComponent::SystemID` when running inside a container.
+    ///
+    /// By default the machine id of the container is used as is, see [`ContainerPolicy`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use machineid_rs::{IdBuilder, Encryption, HWIDComponent, ContainerPolicy};
+    ///
+    /// let mut builder = IdBuilder::new(Encryption::MD5);
+    ///
+    /// builder
+    ///     .add_component(HWIDComponent::SystemID)
+    ///     .container_policy(ContainerPolicy::HostMachineId("/host/etc/machine-id".into()));
+    /// ```
+    pub fn container_policy(&mut self, policy: ContainerPolicy) -> &mut Self {
+        self.container_policy = policy;
+        self
+    }
+
+    /// Makes [`IdBuilder::build`] fail inside a virtual machine unless a `HWIDComponent::FileToken`
+    /// or `HWIDComponent::StoredToken` was added, or the System ID comes from a
+    /// `ContainerPolicy::FileToken` inside a container like WSL2.
+    ///
+    /// VMs cloned from the same image share their machine id, DMI UUID and disk UUIDs,
+    /// while the file token is only created on first use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use machineid_rs::{IdBuilder, Encryption, HWIDComponent};
+    ///
+    /// let mut builder = IdBuilder::new(Encryption::MD5);
+    ///
+    /// builder
+    ///     .add_component(HWIDComponent::SystemID)
+    ///     .require_file_token_in_vm(true);
+    /// ```
+    pub fn require_file_token_in_vm(&mut self, require: bool) -> &mut Self {
+        self.require_file_token_in_vm = require;
+        self
+    }
+
+    /// Adds a machine id that many installs share, i.e. the one baked into a public cloud image.
+    ///
+    /// [`IdBuilder::build_with_report`] reports it as a [`CloneSignal::DefaultMachineId`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use machineid_rs::{IdBuilder, Encryption, HWIDComponent};
+    ///
+    /// let mut builder = IdBuilder::new(Encryption::MD5);
+    ///
+    /// builder
+    ///     .add_component(HWIDComponent::SystemID)
+    ///     .add_known_machine_id("4a1b7c3e9f2d4e8a8b6c5d4e3f2a1b0c");
+    /// ```
+    pub fn add_known_machine_id(&mut self, machine_id: &str) -> &mut Self {
+        self.known_machine_ids.push(machine_id.to_string());
+        self
+    }
+
+    /// Binds the `FileToken` and `StoredToken` components to a hardware component.
+    ///
+    /// New tokens then hold their UUID along with an HMAC, keyed by the key given to [`IdBuilder::build`],
+    /// of the bound components. Only the UUID is used in the ID, even by builders without bindings, so
+    /// removing a binding doesn't change the ID, and a token copied to other hardware is
+    /// reported as a [`CloneSignal::FileTokenMoved`] by [`IdBuilder::build_with_report`].
+    /// Existing plain tokens are still accepted as is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use machineid_rs::{IdBuilder, Encryption, HWIDComponent, TokenLocation, TokenMode};
+    ///
+    /// let mut builder = IdBuilder::new(Encryption::MD5);
+    ///
+    /// builder
+    ///     .add_component(HWIDComponent::StoredToken(
+    ///         TokenLocation::User("myapp".to_string()),
+    ///         TokenMode::CreateIfMissing,
+    ///     ))
+    ///     .add_token_binding(HWIDComponent::SystemID)
+    ///     .add_token_binding(HWIDComponent::MacAddress);
+    /// ```
+    pub fn add_token_binding(&mut self, component: HWIDComponent) -> &mut Self {
+        self.token_bindings.push(component);
+        self
     }
 
     /// Adds a component to the `IdBuilder` that will be hashed once you call the [`IdBuilder::build`] function.
@@ -189,7 +707,7 @@ impl IdBuilder {
         if !self.parts.contains(&component) {
             self.parts.push(component);
         }
-        return self;
+        self
     }
 
     /// Adds all possible components to the `IdBuilder`.
@@ -246,6 +764,10 @@ impl IdBuilder {
         IdBuilder {
             parts: vec![],
             hash,
+            container_policy: ContainerPolicy::default(),
+            require_file_token_in_vm: false,
+            known_machine_ids: vec![],
+            token_bindings: vec![],
         }
     }
 }
@@ -264,7 +786,7 @@ mod test {
             .add_component(HWIDComponent::CPUID)
             .add_component(HWIDComponent::DriveSerial)
             .add_component(HWIDComponent::MacAddress)
-            .add_component(HWIDComponent::FileToken("test.txt"))
+            .add_component(HWIDComponent::FileToken("test.txt".into()))
             .add_component(HWIDComponent::Username)
             .add_component(HWIDComponent::MachineName);
         let hash = builder.build("mykey").unwrap();
@@ -282,7 +804,7 @@ mod test {
             .add_component(HWIDComponent::CPUID)
             .add_component(HWIDComponent::DriveSerial)
             .add_component(HWIDComponent::MacAddress)
-            .add_component(HWIDComponent::FileToken("test.txt"))
+            .add_component(HWIDComponent::FileToken("test.txt".into()))
             .add_component(HWIDComponent::Username)
             .add_component(HWIDComponent::MachineName);
         let hash = builder.build("mykey").unwrap();
@@ -300,11 +822,84 @@ mod test {
             .add_component(HWIDComponent::CPUID)
             .add_component(HWIDComponent::DriveSerial)
             .add_component(HWIDComponent::MacAddress)
-            .add_component(HWIDComponent::FileToken("test.txt"))
+            .add_component(HWIDComponent::FileToken("test.txt".into()))
             .add_component(HWIDComponent::Username)
             .add_component(HWIDComponent::MachineName);
         let hash = builder.build("mykey").unwrap();
         let expected = env::var("MD5_MACHINEID_HASH").unwrap();
         assert_eq!(expected, hash);
     }
+
+    #[test]
+    fn moved_token() {
+        let root = crate::utils::fixture_dir::<&str>(&[]);
+        let path = root.join("token");
+        let mut builder = IdBuilder::new(Encryption::SHA256);
+        builder
+            .add_component(HWIDComponent::FileToken(path.clone()))
+            .add_token_binding(HWIDComponent::Architecture);
+        let report = builder.build_with_report("mykey").unwrap();
+        assert!(!report
+            .clone_signals
+            .contains(&CloneSignal::FileTokenMoved(path.clone())));
+        assert_eq!(builder.build("mykey").unwrap(), report.id);
+
+        let (uuid, _) = open_token(&std::fs::read_to_string(&path).unwrap(), "", "").unwrap();
+        let mut moved = IdBuilder::new(Encryption::SHA256);
+        moved
+            .add_component(HWIDComponent::FileToken(path.clone()))
+            .add_token_binding(HWIDComponent::Architecture)
+            .add_token_binding(HWIDComponent::KernelVersion);
+        let report = moved.build_with_report("mykey").unwrap();
+        assert!(report
+            .clone_signals
+            .contains(&CloneSignal::FileTokenMoved(path.clone())));
+        assert_eq!(report.id, builder.build("mykey").unwrap());
+        assert_eq!(
+            open_token(&std::fs::read_to_string(&path).unwrap(), "", "")
+                .unwrap()
+                .0,
+            uuid
+        );
+    }
+
+    #[test]
+    fn sealed_token_without_bindings() {
+        let root = crate::utils::fixture_dir::<&str>(&[]);
+        let path = root.join("token");
+        let mut bound = IdBuilder::new(Encryption::SHA256);
+        bound
+            .add_component(HWIDComponent::FileToken(path.clone()))
+            .add_token_binding(HWIDComponent::Architecture);
+        let id = bound.build("mykey").unwrap();
+
+        let mut plain = IdBuilder::new(Encryption::SHA256);
+        plain.add_component(HWIDComponent::FileToken(path.clone()));
+        assert_eq!(plain.build("mykey").unwrap(), id);
+
+        let mut stored = IdBuilder::new(Encryption::SHA256);
+        stored.add_component(HWIDComponent::StoredToken(
+            TokenLocation::Path(path),
+            TokenMode::MustExist,
+        ));
+        assert_eq!(stored.build("mykey").unwrap(), id);
+    }
+
+    #[test]
+    fn container_file_token_in_vm() {
+        let wsl = Container::WSL(Some("Ubuntu".to_string()));
+        let mut builder = IdBuilder::new(Encryption::SHA256);
+        builder
+            .add_component(HWIDComponent::SystemID)
+            .container_policy(ContainerPolicy::FileToken("token".into()))
+            .require_file_token_in_vm(true);
+        assert!(builder.uses_file_token(Some(&wsl)));
+        assert!(!builder.uses_file_token(None));
+
+        let mut architecture = IdBuilder::new(Encryption::SHA256);
+        architecture
+            .add_component(HWIDComponent::Architecture)
+            .container_policy(ContainerPolicy::FileToken("token".into()));
+        assert!(!architecture.uses_file_token(Some(&wsl)));
+    }
 }
@@ -1,15 +1,147 @@
 #[cfg(target_os = "linux")]
+use crate::edid::Edid;
+#[cfg(target_os = "linux")]
 use crate::errors::HWIDError;
 #[cfg(target_os = "linux")]
+use crate::gpt::Gpt;
+#[cfg(target_os = "linux")]
+use crate::superblock::read_filesystem_uuid;
+#[cfg(target_os = "linux")]
+use crate::{HostnameOptions, HostnameSource, MemorySource, OSGranularity, Volume};
+#[cfg(target_os = "linux")]
 use serde::Deserialize;
 #[cfg(target_os = "linux")]
-use std::fs::File;
+use sha2::{Digest, Sha256};
+#[cfg(target_os = "linux")]
+use std::collections::HashSet;
+#[cfg(target_os = "linux")]
+use std::fs::{self, File};
+#[cfg(target_os = "linux")]
+use std::net::{IpAddr, ToSocketAddrs};
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
 #[cfg(target_os = "linux")]
 use std::{io::Read, process::Command};
 
 #[cfg(target_os = "linux")]
 const MACHINE_ID_FILES: [&str; 2] = ["/var/lib/dbus/machine-id", "/etc/machine-id"];
 
+#[cfg(target_os = "linux")]
+const CPU_SYSFS_DIR: &str = "/sys/devices/system/cpu";
+
+#[cfg(target_os = "linux")]
+const MEMINFO_FILE: &str = "/proc/meminfo";
+
+#[cfg(target_os = "linux")]
+const DMI_ENTRIES_DIR: &str = "/sys/firmware/dmi/entries";
+
+#[cfg(target_os = "linux")]
+const DEVICE_TREE_DIR: &str = "proc/device-tree";
+
+#[cfg(target_os = "linux")]
+const PCI_SYSFS_DIR: &str = "/sys/bus/pci";
+
+#[cfg(target_os = "linux")]
+const DRM_SYSFS_DIR: &str = "/sys/class/drm";
+
+#[cfg(target_os = "linux")]
+const BLOCK_SYSFS_DIR: &str = "/sys/block";
+
+#[cfg(target_os = "linux")]
+const MOUNTINFO_FILE: &str = "/proc/self/mountinfo";
+
+#[cfg(target_os = "linux")]
+const OS_RELEASE_FILES: [&str; 2] = ["/etc/os-release", "/usr/lib/os-release"];
+
+#[cfg(target_os = "linux")]
+const HOSTID_FILE: &str = "/etc/hostid";
+
+#[cfg(target_os = "linux")]
+const KERNEL_HOSTNAME_FILE: &str = "/proc/sys/kernel/hostname";
+
+#[cfg(target_os = "linux")]
+const STATIC_HOSTNAME_FILE: &str = "etc/hostname";
+
+#[cfg(target_os = "linux")]
+const MACHINE_INFO_FILE: &str = "etc/machine-info";
+
+#[cfg(target_os = "linux")]
+const NET_SYSFS_DIR: &str = "/sys/class/net";
+
+/// `SIOCETHTOOL` ioctl and its `ETHTOOL_GPERMADDR` command, reading the address burnt into the NIC
+#[cfg(target_os = "linux")]
+const SIOCETHTOOL: u64 = 0x8946;
+#[cfg(target_os = "linux")]
+const ETHTOOL_GPERMADDR: u32 = 0x20;
+#[cfg(target_os = "linux")]
+const MAX_ADDR_LEN: usize = 32;
+
+#[cfg(target_os = "linux")]
+const DMI_ID_DIR: &str = "/sys/class/dmi/id";
+
+#[cfg(target_os = "linux")]
+const DMI_ID_FIELDS: [&str; 8] = [
+    "sys_vendor",
+    "product_name",
+    "product_serial",
+    "product_uuid",
+    "board_vendor",
+    "board_name",
+    "board_serial",
+    "chassis_serial",
+];
+
+/// Values firmware vendors leave in DMI fields they didn't fill
+#[cfg(target_os = "linux")]
+const DMI_PLACEHOLDERS: [&str; 14] = [
+    "To Be Filled By O.E.M.",
+    "Default string",
+    "System manufacturer",
+    "System Product Name",
+    "System Serial Number",
+    "Base Board Serial Number",
+    "Chassis Serial Number",
+    "Not Specified",
+    "Not Applicable",
+    "None",
+    "0123456789",
+    "03000200-0400-0500-0006-000700080009",
+    "00000000-0000-0000-0000-000000000000",
+    "ffffffff-ffff-ffff-ffff-ffffffffffff",
+];
+
+/// `(sys_vendor, file, prefix)` of the files holding the cloud instance ID, relative to the root.
+/// A file is only read on its provider, and its value must start with the prefix, so
+/// bare-metal machines don't return their asset tag.
+#[cfg(target_os = "linux")]
+const CLOUD_INSTANCE_ID_FILES: [(&str, &str, &str); 2] = [
+    // EC2 instances on Xen, which don't always set the DMI vendor
+    ("", "sys/hypervisor/uuid", "ec2"),
+    // EC2 instances on Nitro
+    ("Amazon EC2", "sys/class/dmi/id/board_asset_tag", "i-"),
+];
+
+/// Instance ID written by cloud-init, on any provider, relative to the root
+#[cfg(target_os = "linux")]
+const CLOUD_INIT_INSTANCE_ID_FILE: &str = "var/lib/cloud/data/instance-id";
+
+/// Values of the instance ID sources that are the same on every instance
+#[cfg(target_os = "linux")]
+const CLOUD_SHARED_IDS: [&str; 2] = [
+    "00000000-0000-0000-0000-000000000000",
+    // cloud-init without a datasource
+    "iid-datasource-none",
+];
+
+#[cfg(target_os = "linux")]
+const EFI_SYSFS_DIR: &str = "/sys/firmware/efi";
+
+#[cfg(target_os = "linux")]
+const EFI_GLOBAL_GUID: &str = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+
+#[cfg(target_os = "linux")]
+const EFI_LOADER_GUID: &str = "4a67b082-0a4c-41cf-b6c7-440b29bb8c4f";
+
 #[cfg(target_os = "linux")]
 #[derive(Deserialize)]
 struct Output {
@@ -26,59 +158,254 @@ struct Device {
     children: Option<Vec<Self>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(rename = "maj:min", skip_serializing_if = "Option::is_none")]
+    maj_min: Option<String>,
+}
+
+#[cfg(target_os = "linux")]
+impl Device {
+    fn find<F: Fn(&Device) -> bool>(&self, predicate: &F) -> Option<&Device> {
+        if predicate(self) {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .flatten()
+            .find_map(|child| child.find(predicate))
+    }
 }
 
 #[cfg(target_os = "linux")]
 impl Output {
     #[cfg(target_os = "linux")]
-    fn get_root(self) -> Result<String, HWIDError> {
-        for devc in self.blockdevices.into_iter() {
-            if let Some(mountpoint) = devc.mountpoint {
-                if mountpoint.eq("/") {
-                    // If the main disk is a sdcard, it's much safer to use the hardware cid over partition uuid
-                    if devc.name.contains("mmc") {
-                        let disk_name = &devc.name[0..devc.name.len() - 2];
-                        let uuid =
-                            std::fs::read_to_string(format!("/sys/block/{disk_name}/device/cid"))
-                                .unwrap_or_default()
-                                .trim()
-                                .to_string();
-                        if uuid.len() == 32 {
-                            return Ok(uuid);
-                        }
-                    }
-                    if let Some(uuid) = devc.uuid {
-                        return Ok(uuid);
-                    }
-                }
-            }
-            if let Some(children) = devc.children {
-                for chld_device in children.into_iter() {
-                    if let Some(mnt) = chld_device.mountpoint {
-                        if mnt.eq("/") {
-                            if let Some(uuid) = chld_device.uuid {
-                                return Ok(uuid);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Err(HWIDError::new(
+    fn get_root(&self) -> Result<String, HWIDError> {
+        let (device, _) =
+            self.find_volume(&Volume::MountPoint("/".into()))
+                .ok_or(HWIDError::new(
+                    "UuidError",
+                    "Could not find root disk's UUID",
+                ))?;
+        device.uuid.clone().ok_or(HWIDError::new(
             "UuidError",
             "Could not find root disk's UUID",
         ))
     }
+
+    /// Finds the device holding the given volume, along with the top-level disk it belongs to.
+    #[cfg(target_os = "linux")]
+    fn find_volume(&self, volume: &Volume) -> Option<(&Device, &Device)> {
+        let predicate = |device: &Device| match volume {
+            Volume::MountPoint(path) => device
+                .mountpoint
+                .as_deref()
+                .is_some_and(|mnt| Path::new(mnt) == path.as_path()),
+            Volume::Label(label) => device.label.as_deref() == Some(label.as_str()),
+        };
+        self.blockdevices
+            .iter()
+            .find_map(|disk| disk.find(&predicate).map(|device| (device, disk)))
+    }
+
+    /// Finds the device holding a mounted filesystem, along with the top-level disk it belongs to.
+    ///
+    /// lsblk only lists one mount point per device, so bind mounts and btrfs subvolumes are
+    /// matched on the device number, or on the mount source for btrfs, whose device number is anonymous.
+    #[cfg(target_os = "linux")]
+    fn find_mounted(&self, mount: &MountInfo) -> Option<(&Device, &Device)> {
+        let by_number = |device: &Device| device.maj_min.as_deref() == Some(mount.device.as_str());
+        let source = Path::new(&mount.source);
+        let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+        let by_source = |device: &Device| {
+            source.starts_with("/dev") && source.file_name() == Some(device.name.as_ref())
+        };
+        self.blockdevices
+            .iter()
+            .find_map(|disk| disk.find(&by_number).map(|device| (device, disk)))
+            .or_else(|| {
+                self.blockdevices
+                    .iter()
+                    .find_map(|disk| disk.find(&by_source).map(|device| (device, disk)))
+            })
+    }
 }
 
+/// Reads the hardware CID of a sdcard, which is much safer than a partition UUID.
 #[cfg(target_os = "linux")]
-pub(crate) fn get_disk_id() -> Result<String, HWIDError> {
-    let output = run_command("lsblk -f -J -o NAME,MOUNTPOINT,UUID")?;
+fn get_mmc_cid(block_dir: &Path, disk_name: &str) -> Option<String> {
+    if !disk_name.starts_with("mmc") {
+        return None;
+    }
+    let cid = get_file_content(block_dir.join(disk_name).join("device/cid")).ok()?;
+    let cid = cid.trim();
+    (cid.len() == 32).then(|| cid.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn get_block_devices() -> Result<Output, HWIDError> {
+    let output = run_command("lsblk -f -J -o NAME,MOUNTPOINT,UUID,LABEL,MAJ:MIN")?;
 
     let output_string = String::from_utf8(output.into())?;
     let parsed: Output = serde_json::from_str(output_string.as_str())?;
-    let uuid = parsed.get_root()?;
-    Ok(uuid)
+    Ok(parsed)
+}
+
+/// A line of `/proc/self/mountinfo`.
+#[cfg(target_os = "linux")]
+struct MountInfo {
+    /// `major:minor` of the device holding the filesystem
+    device: String,
+    mount_point: PathBuf,
+    /// The mounted device, i.e. `/dev/sda1`
+    source: String,
+}
+
+/// Undoes the octal escaping (`\040` for spaces) used in mountinfo paths.
+#[cfg(target_os = "linux")]
+fn unescape_mount_path(path: &str) -> PathBuf {
+    let mut bytes = vec![];
+    let mut chars = path.as_bytes().iter();
+    while let Some(c) = chars.next() {
+        if *c == b'\\' {
+            let digits = chars.clone().take(3).copied().collect::<Vec<u8>>();
+            if digits.len() == 3 && digits.iter().all(|d| (b'0'..=b'7').contains(d)) {
+                let code = digits
+                    .iter()
+                    .fold(0u8, |n, d| n.wrapping_mul(8) + (d - b'0'));
+                bytes.push(code);
+                chars.nth(2);
+                continue;
+            }
+        }
+        bytes.push(*c);
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn parse_mountinfo(content: &str) -> Vec<MountInfo> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (mount, filesystem) = line.split_once(" - ")?;
+            let mount = mount.split(' ').collect::<Vec<&str>>();
+            let source = filesystem.split(' ').nth(1)?;
+            Some(MountInfo {
+                device: mount.get(2)?.to_string(),
+                mount_point: unescape_mount_path(mount.get(4)?),
+                source: unescape_mount_path(source).to_string_lossy().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn get_mountinfo() -> Result<Vec<MountInfo>, HWIDError> {
+    Ok(parse_mountinfo(&get_file_content(MOUNTINFO_FILE)?))
+}
+
+/// Finds the mount holding `path`, i.e. the one with the longest mount point above it.
+#[cfg(target_os = "linux")]
+fn find_mount<'a>(mounts: &'a [MountInfo], path: &Path) -> Option<&'a MountInfo> {
+    // max_by_key keeps the last match, as later entries shadow earlier ones mounted at the same place
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+#[cfg(target_os = "linux")]
+fn get_disk_id_at_from(
+    block_dir: &Path,
+    mounts: &[MountInfo],
+    devices: &Output,
+    path: &Path,
+) -> Result<String, HWIDError> {
+    let mount = find_mount(mounts, path).ok_or(HWIDError::new(
+        "UuidError",
+        &format!("Could not find the filesystem holding {}", path.display()),
+    ))?;
+    let (device, disk) = devices.find_mounted(mount).ok_or(HWIDError::new(
+        "UuidError",
+        &format!(
+            "Could not find the disk mounted at {}",
+            mount.mount_point.display()
+        ),
+    ))?;
+    if let Some(cid) = get_mmc_cid(block_dir, &disk.name) {
+        return Ok(cid);
+    }
+    device.uuid.clone().ok_or(HWIDError::new(
+        "UuidError",
+        &format!(
+            "The disk mounted at {} has no UUID",
+            mount.mount_point.display()
+        ),
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_disk_id_at(path: &Path) -> Result<String, HWIDError> {
+    get_disk_id_at_from(
+        Path::new(BLOCK_SYSFS_DIR),
+        &get_mountinfo()?,
+        &get_block_devices()?,
+        &fs::canonicalize(path)?,
+    )
+}
+
+/// Finds the device node of a mount from its `major:minor`, as the mount source may be `/dev/root`.
+#[cfg(target_os = "linux")]
+fn get_mount_device(root: &Path, mount: &MountInfo) -> Result<PathBuf, HWIDError> {
+    let uevent = get_file_content(
+        root.join("sys/dev/block")
+            .join(&mount.device)
+            .join("uevent"),
+    )?;
+    let name = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("DEVNAME="))
+        .ok_or(HWIDError::new(
+            "UuidError",
+            &format!("Could not find the device node of {}", mount.device),
+        ))?;
+    Ok(fs::canonicalize(root.join("dev").join(name))?)
+}
+
+#[cfg(target_os = "linux")]
+fn get_udev_uuid(root: &Path, device: &Path) -> Result<String, HWIDError> {
+    for entry in fs::read_dir(root.join("dev/disk/by-uuid"))?.flatten() {
+        if fs::canonicalize(entry.path()).ok().as_deref() == Some(device) {
+            return Ok(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Err(HWIDError::new(
+        "UuidError",
+        &format!("{} is not listed in /dev/disk/by-uuid", device.display()),
+    ))
+}
+
+/// Finds the root UUID through udev's symlinks, or straight from the filesystem superblock.
+#[cfg(target_os = "linux")]
+fn get_disk_id_without_lsblk_from(root: &Path, mounts: &[MountInfo]) -> Result<String, HWIDError> {
+    let mount = find_mount(mounts, Path::new("/")).ok_or(HWIDError::new(
+        "UuidError",
+        "Could not find the root filesystem",
+    ))?;
+    let device = get_mount_device(root, mount)?;
+    get_udev_uuid(root, &device).or_else(|_| read_filesystem_uuid(&mut File::open(&device)?))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_disk_id() -> Result<String, HWIDError> {
+    match get_block_devices().and_then(|devices| devices.get_root()) {
+        Ok(uuid) => Ok(uuid),
+        // lsblk is usually missing in initramfs and minimal containers
+        Err(error) => get_mountinfo()
+            .and_then(|mounts| get_disk_id_without_lsblk_from(Path::new("/"), &mounts))
+            .map_err(|_| error),
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -90,7 +417,7 @@ fn run_command(command: &str) -> Result<String, HWIDError> {
     if !cmd.status()?.success() {
         return Err(HWIDError::new(
             &format!("Failed to run command: {command}"),
-            &String::from_utf8(output.stderr.into())?,
+            &String::from_utf8(output.stderr)?,
         ));
     }
 
@@ -99,7 +426,7 @@ fn run_command(command: &str) -> Result<String, HWIDError> {
 
 #[cfg(target_os = "linux")]
 fn get_mac_addressof_interface(interface_name: &str) -> Result<String, HWIDError> {
-    get_file_content(&format!("/sys/class/net/{interface_name}/address"))
+    get_file_content(format!("/sys/class/net/{interface_name}/address"))
 }
 
 #[cfg(target_os = "linux")]
@@ -139,23 +466,1597 @@ pub(crate) fn get_mac_address() -> Result<String, HWIDError> {
 }
 
 #[cfg(target_os = "linux")]
-fn get_file_content(path: &str) -> Result<String, HWIDError> {
+fn get_file_content<P: AsRef<Path>>(path: P) -> Result<String, HWIDError> {
     let mut file = File::open(path)?;
     let mut content = String::new();
     file.read_to_string(&mut content)?;
     Ok(content)
 }
 
+/// The file the System ID is read from.
+#[cfg(target_os = "linux")]
+pub(crate) fn get_machine_id_file() -> Option<&'static Path> {
+    MACHINE_ID_FILES
+        .iter()
+        .map(Path::new)
+        .find(|path| path.exists())
+}
+
 #[cfg(target_os = "linux")]
 pub(crate) fn get_hwid() -> Result<String, HWIDError> {
-    for path in MACHINE_ID_FILES.iter() {
-        if std::path::Path::new(path).exists() {
-            let content = get_file_content(path)?;
-            return Ok(content);
+    match get_machine_id_file() {
+        Some(path) => get_file_content(path),
+        None => Err(HWIDError::new(
+            "FileNotFound",
+            "Could not find the files containing the System ID",
+        )),
+    }
+}
+
+/// CPU topology and cache sizes as reported by the kernel in sysfs.
+#[cfg(target_os = "linux")]
+pub(crate) struct CpuTopology {
+    pub(crate) sockets: usize,
+    pub(crate) cores: usize,
+    pub(crate) threads: usize,
+    pub(crate) l2_cache_kb: u64,
+    pub(crate) l3_cache_kb: u64,
+}
+
+/// Parses a kernel cpu list such as `0-3,6,8-11`.
+#[cfg(target_os = "linux")]
+fn parse_cpu_list(list: &str) -> Result<Vec<usize>, HWIDError> {
+    let invalid = || HWIDError::new("CpuListError", &format!("Invalid cpu list: {list}"));
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.parse().map_err(|_| invalid())?;
+                let end: usize = end.parse().map_err(|_| invalid())?;
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(range.parse().map_err(|_| invalid())?),
         }
     }
-    Err(HWIDError::new(
-        "FileNotFound",
-        "Could not find the files containing the System ID",
+    Ok(cpus)
+}
+
+/// Parses a sysfs cache size such as `512K` into KiB.
+#[cfg(target_os = "linux")]
+fn parse_cache_size(size: &str) -> Result<u64, HWIDError> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last() {
+        Some('K') => (&size[..size.len() - 1], 1),
+        Some('M') => (&size[..size.len() - 1], 1024),
+        Some('G') => (&size[..size.len() - 1], 1024 * 1024),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .map(|n| n * multiplier)
+        .map_err(|_| HWIDError::new("CacheSizeError", &format!("Invalid cache size: {size}")))
+}
+
+#[cfg(target_os = "linux")]
+fn get_cpu_topology_from(cpu_dir: &Path) -> Result<CpuTopology, HWIDError> {
+    let cpus = parse_cpu_list(&get_file_content(cpu_dir.join("online"))?)?;
+    if cpus.is_empty() {
+        return Err(HWIDError::new(
+            "CpuTopologyError",
+            "The kernel reported no online CPUs",
+        ));
+    }
+
+    let mut packages = HashSet::new();
+    let mut cores = HashSet::new();
+    // A cache shared by several CPUs is listed under each of them, so it is
+    // only counted once per (level, shared_cpu_list) pair.
+    let mut caches = HashSet::new();
+    let (mut l2_cache_kb, mut l3_cache_kb) = (0, 0);
+
+    for cpu in cpus.iter() {
+        let cpu_path = cpu_dir.join(format!("cpu{cpu}"));
+        let package = get_file_content(cpu_path.join("topology/physical_package_id"))?;
+        let core = get_file_content(cpu_path.join("topology/core_id"))?;
+        packages.insert(package.trim().to_string());
+        cores.insert((package.trim().to_string(), core.trim().to_string()));
+
+        let entries = match fs::read_dir(cpu_path.join("cache")) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let cache_path = entry.path();
+            if !entry.file_name().to_string_lossy().starts_with("index") {
+                continue;
+            }
+            let cache_type = get_file_content(cache_path.join("type")).unwrap_or_default();
+            if cache_type.trim() == "Instruction" {
+                continue;
+            }
+            let level = get_file_content(cache_path.join("level"))?;
+            let shared = get_file_content(cache_path.join("shared_cpu_list"))
+                .unwrap_or_else(|_| cpu.to_string());
+            if !caches.insert((level.trim().to_string(), shared.trim().to_string())) {
+                continue;
+            }
+            let size = parse_cache_size(&get_file_content(cache_path.join("size"))?)?;
+            match level.trim() {
+                "2" => l2_cache_kb += size,
+                "3" => l3_cache_kb += size,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(CpuTopology {
+        sockets: packages.len(),
+        cores: cores.len(),
+        threads: cpus.len(),
+        l2_cache_kb,
+        l3_cache_kb,
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_cpu_topology() -> Result<CpuTopology, HWIDError> {
+    get_cpu_topology_from(Path::new(CPU_SYSFS_DIR))
+}
+
+/// Sums the sizes of the SMBIOS memory devices (type 17) in MiB.
+#[cfg(target_os = "linux")]
+fn get_smbios_memory_mb(entries_dir: &Path) -> Result<u64, HWIDError> {
+    let mut total = 0;
+    for entry in fs::read_dir(entries_dir)?.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("17-") {
+            continue;
+        }
+        let raw = fs::read(entry.path().join("raw"))?;
+        if raw.len() < 0x0E {
+            continue;
+        }
+        total += match u16::from_le_bytes([raw[0x0C], raw[0x0D]]) {
+            // Empty slot or unknown size
+            0 | 0xFFFF => 0,
+            // The real size is stored in the Extended Size field
+            0x7FFF if raw.len() >= 0x20 => {
+                (u32::from_le_bytes([raw[0x1C], raw[0x1D], raw[0x1E], raw[0x1F]]) & 0x7FFF_FFFF)
+                    as u64
+            }
+            0x7FFF => 0,
+            // Bit 15 set means the size is given in KiB
+            size if size & 0x8000 != 0 => (size & 0x7FFF) as u64 / 1024,
+            size => size as u64,
+        };
+    }
+    if total == 0 {
+        return Err(HWIDError::new(
+            "MemoryError",
+            "No SMBIOS memory devices found",
+        ));
+    }
+    Ok(total)
+}
+
+/// Reads `MemTotal` from a meminfo file, in MiB.
+#[cfg(target_os = "linux")]
+fn get_meminfo_mb(meminfo: &Path) -> Result<u64, HWIDError> {
+    get_file_content(meminfo)?
+        .lines()
+        .find_map(|line| {
+            let value = line.strip_prefix("MemTotal:")?;
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb / 1024)
+        .ok_or(HWIDError::new(
+            "MemoryError",
+            "Could not find MemTotal in meminfo",
+        ))
+}
+
+/// Rounds `size` up to the next multiple of `granularity`, so the memory the
+/// kernel reserves for itself does not change the result.
+#[cfg(target_os = "linux")]
+fn round_memory(size: u64, granularity: u64) -> u64 {
+    let granularity = granularity.max(1);
+    size.div_ceil(granularity) * granularity
+}
+
+#[cfg(target_os = "linux")]
+fn get_installed_memory_from(
+    entries_dir: &Path,
+    meminfo: &Path,
+    source: &MemorySource,
+    granularity: u64,
+) -> Result<String, HWIDError> {
+    let size = match source {
+        MemorySource::MemTotal => get_meminfo_mb(meminfo)?,
+        MemorySource::SMBIOS => get_smbios_memory_mb(entries_dir)?,
+    };
+    Ok(round_memory(size, granularity).to_string())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_installed_memory(
+    source: &MemorySource,
+    granularity: u64,
+) -> Result<String, HWIDError> {
+    get_installed_memory_from(
+        Path::new(DMI_ENTRIES_DIR),
+        Path::new(MEMINFO_FILE),
+        source,
+        granularity,
+    )
+}
+
+/// Reads a device-tree property, which is a list of NUL terminated strings.
+#[cfg(target_os = "linux")]
+fn get_device_tree_strings(root: &Path, property: &str) -> Result<Vec<String>, HWIDError> {
+    let content = fs::read(root.join(DEVICE_TREE_DIR).join(property))?;
+    let strings = content
+        .split(|b| *b == 0)
+        .map(|s| String::from_utf8_lossy(s).trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<String>>();
+    if strings.is_empty() {
+        return Err(HWIDError::new(
+            "DeviceTreeError",
+            &format!("The device-tree property {property} is empty"),
+        ));
+    }
+    Ok(strings)
+}
+
+#[cfg(target_os = "linux")]
+fn get_soc_serial_from(root: &Path) -> Result<String, HWIDError> {
+    Ok(get_device_tree_strings(root, "serial-number")?.join(""))
+}
+
+#[cfg(target_os = "linux")]
+fn get_cpuinfo_serial_from(root: &Path) -> Result<String, HWIDError> {
+    get_file_content(root.join("proc/cpuinfo"))?
+        .lines()
+        .find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim() == "Serial").then(|| value.trim().to_string())
+        })
+        // Boards without a serial fuse report a string of zeros
+        .filter(|serial| !serial.is_empty() && serial.chars().any(|c| c != '0'))
+        .ok_or(HWIDError::new(
+            "SerialError",
+            "Could not find a Serial in /proc/cpuinfo",
+        ))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_soc_serial() -> Result<String, HWIDError> {
+    get_soc_serial_from(Path::new("/"))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_cpuinfo_serial() -> Result<String, HWIDError> {
+    get_cpuinfo_serial_from(Path::new("/"))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_board_model() -> Result<String, HWIDError> {
+    Ok(get_device_tree_strings(Path::new("/"), "model")?.join(";"))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_board_compatible() -> Result<String, HWIDError> {
+    Ok(get_device_tree_strings(Path::new("/"), "compatible")?.join(";"))
+}
+
+/// Addresses (`domain:bus:device`) of the hotplug capable PCI slots.
+#[cfg(target_os = "linux")]
+fn get_pci_hotplug_slots(pci_dir: &Path) -> HashSet<String> {
+    fs::read_dir(pci_dir.join("slots"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| get_file_content(entry.path().join("address")).ok())
+                .map(|address| address.trim().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn get_pci_devices_from(pci_dir: &Path, excluded_classes: &[u8]) -> Result<String, HWIDError> {
+    let hotplug_slots = get_pci_hotplug_slots(pci_dir);
+    let mut devices = vec![];
+    for entry in fs::read_dir(pci_dir.join("devices"))?.flatten() {
+        let slot = entry.file_name().to_string_lossy().to_string();
+        let device_path = entry.path();
+        let read = |attribute: &str| -> Result<String, HWIDError> {
+            Ok(get_file_content(device_path.join(attribute))?
+                .trim()
+                .trim_start_matches("0x")
+                .to_string())
+        };
+
+        let class = u32::from_str_radix(&read("class")?, 16).map_err(|_| {
+            HWIDError::new("PciError", &format!("Invalid class for PCI device {slot}"))
+        })?;
+        if excluded_classes.contains(&((class >> 16) as u8)) {
+            continue;
+        }
+        // Skip devices sitting in a hotplug slot or flagged as removable by the kernel
+        let slot_address = slot.rsplit_once('.').map(|(a, _)| a).unwrap_or(&slot);
+        if hotplug_slots.contains(slot_address)
+            || read("removable").map(|r| r == "removable").unwrap_or(false)
+        {
+            continue;
+        }
+
+        devices.push(format!(
+            "{slot} {}:{} {}:{}",
+            read("vendor")?,
+            read("device")?,
+            read("subsystem_vendor").unwrap_or_default(),
+            read("subsystem_device").unwrap_or_default(),
+        ));
+    }
+    if devices.is_empty() {
+        return Err(HWIDError::new("PciError", "No PCI devices found"));
+    }
+    devices.sort();
+
+    let mut hasher = Sha256::new();
+    for device in devices.iter() {
+        hasher.update(device.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_pci_devices(excluded_classes: &[u8]) -> Result<String, HWIDError> {
+    get_pci_devices_from(Path::new(PCI_SYSFS_DIR), excluded_classes)
+}
+
+#[cfg(target_os = "linux")]
+fn get_monitor_edid_from(drm_dir: &Path) -> Result<String, HWIDError> {
+    let mut monitors = vec![];
+    let mut parse_error = None;
+    for entry in fs::read_dir(drm_dir)?.flatten() {
+        let connector = entry.path();
+        let status = get_file_content(connector.join("status")).unwrap_or_default();
+        if status.trim() != "connected" {
+            continue;
+        }
+        let data = match fs::read(connector.join("edid")) {
+            Ok(data) if !data.is_empty() => data,
+            _ => continue,
+        };
+        // KVM switches, docks and adapters often pass a truncated or corrupt EDID
+        match Edid::parse(&data) {
+            Ok(edid) => monitors.push(edid.identifier()),
+            Err(e) => parse_error = Some(e),
+        }
+    }
+    if monitors.is_empty() {
+        return Err(parse_error.unwrap_or(HWIDError::new(
+            "EdidError",
+            "Could not find any connected display",
+        )));
+    }
+    // The same display can show up on several connectors (e.g. DP and DP-MST)
+    monitors.sort();
+    monitors.dedup();
+    Ok(monitors.join(";"))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_monitor_edid() -> Result<String, HWIDError> {
+    get_monitor_edid_from(Path::new(DRM_SYSFS_DIR))
+}
+
+/// Walks up the sysfs device tree of a disk to the USB device it is attached to.
+#[cfg(target_os = "linux")]
+fn get_usb_device_ids(block_dir: &Path, disk_name: &str) -> Result<String, HWIDError> {
+    let device_path = fs::canonicalize(block_dir.join(disk_name).join("device"))?;
+    let usb_device = device_path
+        .ancestors()
+        .find(|dir| dir.join("idVendor").exists())
+        .ok_or(HWIDError::new(
+            "UsbError",
+            &format!("{disk_name} is not a USB device"),
+        ))?;
+    let read = |attribute: &str| {
+        get_file_content(usb_device.join(attribute)).map(|value| value.trim().to_string())
+    };
+    Ok(format!(
+        "{}:{}:{}",
+        read("idVendor")?,
+        read("idProduct")?,
+        read("serial").unwrap_or_default()
     ))
 }
+
+#[cfg(target_os = "linux")]
+fn get_usb_volume_id_from(
+    block_dir: &Path,
+    devices: &Output,
+    volume: &Volume,
+) -> Result<String, HWIDError> {
+    let (device, disk) = devices.find_volume(volume).ok_or(HWIDError::new(
+        "UsbError",
+        &format!("Could not find the volume {volume:?}"),
+    ))?;
+    let uuid = device.uuid.as_deref().ok_or(HWIDError::new(
+        "UuidError",
+        &format!("The volume {volume:?} has no filesystem UUID"),
+    ))?;
+    Ok(format!(
+        "{}:{uuid}",
+        get_usb_device_ids(block_dir, &disk.name)?
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_usb_volume_id(volume: &Volume) -> Result<String, HWIDError> {
+    get_usb_volume_id_from(Path::new(BLOCK_SYSFS_DIR), &get_block_devices()?, volume)
+}
+
+/// Reads a `KEY=value` entry of a sysfs uevent file.
+#[cfg(target_os = "linux")]
+fn get_uevent_value(uevent: &Path, key: &str) -> Result<String, HWIDError> {
+    get_file_content(uevent)?
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .map(|value| value.to_string())
+        .ok_or(HWIDError::new(
+            "UeventError",
+            &format!("Could not find {key} in {}", uevent.display()),
+        ))
+}
+
+/// Finds the disk device node holding the root filesystem and the root partition number.
+#[cfg(target_os = "linux")]
+fn get_root_partition_from(root: &Path, mounts: &[MountInfo]) -> Result<(PathBuf, u32), HWIDError> {
+    let mount = find_mount(mounts, Path::new("/")).ok_or(HWIDError::new(
+        "GptError",
+        "Could not find the root filesystem",
+    ))?;
+    // The sysfs directory of a partition lives inside the one of its disk
+    let partition = fs::canonicalize(root.join("sys/dev/block").join(&mount.device))?;
+    let number = get_uevent_value(&partition.join("uevent"), "PARTN")?
+        .parse::<u32>()
+        .map_err(|_| HWIDError::new("GptError", "Invalid root partition number"))?;
+    let disk = partition
+        .parent()
+        .ok_or(HWIDError::new("GptError", "Could not find the root disk"))?;
+    let disk_name = get_uevent_value(&disk.join("uevent"), "DEVNAME")?;
+    Ok((root.join("dev").join(disk_name), number))
+}
+
+#[cfg(target_os = "linux")]
+fn get_root_gpt_from(root: &Path, mounts: &[MountInfo]) -> Result<(Gpt, u32), HWIDError> {
+    let (disk, number) = get_root_partition_from(root, mounts)?;
+    Ok((Gpt::read(&mut File::open(disk)?)?, number))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_disk_guid() -> Result<String, HWIDError> {
+    let (gpt, _) = get_root_gpt_from(Path::new("/"), &get_mountinfo()?)?;
+    Ok(gpt.disk_guid)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_root_partuuid() -> Result<String, HWIDError> {
+    let (gpt, number) = get_root_gpt_from(Path::new("/"), &get_mountinfo()?)?;
+    gpt.partition(number)
+        .map(|partition| partition.unique_guid.clone())
+        .ok_or(HWIDError::new(
+            "GptError",
+            "The root partition is not in the GPT",
+        ))
+}
+
+/// Reads an EFI variable from efivarfs, without its 4 bytes of attributes.
+#[cfg(target_os = "linux")]
+fn get_efi_variable(efi_dir: &Path, name: &str, guid: &str) -> Result<Vec<u8>, HWIDError> {
+    let content = fs::read(efi_dir.join("efivars").join(format!("{name}-{guid}")))?;
+    if content.len() <= 4 {
+        return Err(HWIDError::new(
+            "EfiError",
+            &format!("The EFI variable {name} is empty"),
+        ));
+    }
+    Ok(content[4..].to_vec())
+}
+
+/// Reads a boolean EFI variable such as `SecureBoot`.
+#[cfg(target_os = "linux")]
+fn get_efi_flag_from(efi_dir: &Path, name: &str) -> Result<String, HWIDError> {
+    let value = get_efi_variable(efi_dir, name, EFI_GLOBAL_GUID)?;
+    Ok(if value[0] != 0 { "1" } else { "0" }.to_string())
+}
+
+/// Reads the PARTUUID of the ESP the boot loader was started from, as set by systemd-boot and others.
+#[cfg(target_os = "linux")]
+fn get_loader_partuuid_from(efi_dir: &Path) -> Result<String, HWIDError> {
+    let value = get_efi_variable(efi_dir, "LoaderDevicePartUUID", EFI_LOADER_GUID)?;
+    let utf16 = value
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|c| *c != 0)
+        .collect::<Vec<u16>>();
+    String::from_utf16(&utf16)
+        .map(|uuid| uuid.to_lowercase())
+        .map_err(|_| HWIDError::new("EfiError", "Invalid LoaderDevicePartUUID"))
+}
+
+#[cfg(target_os = "linux")]
+fn get_boot_mode_from(efi_dir: &Path) -> String {
+    match efi_dir.is_dir() {
+        true => "uefi".to_string(),
+        false => "bios".to_string(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_boot_mode() -> Result<String, HWIDError> {
+    Ok(get_boot_mode_from(Path::new(EFI_SYSFS_DIR)))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_secure_boot() -> Result<String, HWIDError> {
+    get_efi_flag_from(Path::new(EFI_SYSFS_DIR), "SecureBoot")
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_setup_mode() -> Result<String, HWIDError> {
+    get_efi_flag_from(Path::new(EFI_SYSFS_DIR), "SetupMode")
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_loader_partuuid() -> Result<String, HWIDError> {
+    get_loader_partuuid_from(Path::new(EFI_SYSFS_DIR))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_esp_partuuid() -> Result<String, HWIDError> {
+    let (gpt, _) = get_root_gpt_from(Path::new("/"), &get_mountinfo()?)?;
+    gpt.esp()
+        .map(|partition| partition.unique_guid.clone())
+        .ok_or(HWIDError::new(
+            "GptError",
+            "Could not find an EFI System Partition on the root disk",
+        ))
+}
+
+/// Computes the POSIX hostid the same way glibc's `gethostid` does.
+///
+/// The id is read from the hostid file when it holds at least 4 bytes, otherwise it is
+/// derived from the first IPv4 address of the hostname, and is 0 if there is none.
+#[cfg(target_os = "linux")]
+fn get_hostid_from(hostid_file: &Path, hostname: &str) -> String {
+    if let Ok(content) = fs::read(hostid_file) {
+        if content.len() >= 4 {
+            // Written by sethostid in native byte order, i.e. little-endian on x86 and ARM
+            let id = u32::from_ne_bytes([content[0], content[1], content[2], content[3]]);
+            return format!("{id:08x}");
+        }
+    }
+    let address = (hostname, 0)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addresses| {
+            addresses.find_map(|address| match address.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                IpAddr::V6(_) => None,
+            })
+        });
+    let id = match address {
+        // glibc swaps the two halves of the in_addr, which is in network byte order
+        Some(ip) => u32::from_ne_bytes(ip.octets()).rotate_left(16),
+        None => 0,
+    };
+    format!("{id:08x}")
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_hostid() -> Result<String, HWIDError> {
+    let hostname = get_file_content(KERNEL_HOSTNAME_FILE)?;
+    Ok(get_hostid_from(Path::new(HOSTID_FILE), hostname.trim()))
+}
+
+/// Parses an environment-like file such as os-release into its `KEY=value` pairs, removing the shell quoting.
+#[cfg(target_os = "linux")]
+fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => value
+                    .strip_prefix(quote)
+                    .and_then(|value| value.strip_suffix(quote))
+                    .unwrap_or(value)
+                    .replace(&format!("\\{quote}"), &quote.to_string())
+                    .replace("\\\\", "\\"),
+                _ => value.to_string(),
+            };
+            (key.trim().to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn get_os_release_from(files: &[&Path], granularity: &OSGranularity) -> Result<String, HWIDError> {
+    let content = files
+        .iter()
+        .find_map(|path| get_file_content(path).ok())
+        .ok_or(HWIDError::new(
+            "OSReleaseError",
+            "Could not find the os-release file",
+        ))?;
+    let fields = parse_env_file(&content);
+    let field = |key: &str| {
+        fields
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .filter(|v| !v.is_empty())
+    };
+    // "linux" is the default ID according to the os-release specification
+    let id = field("ID").unwrap_or("linux");
+
+    Ok(match granularity {
+        OSGranularity::Family => field("ID_LIKE")
+            .and_then(|like| like.split_whitespace().last())
+            .unwrap_or(id)
+            .to_string(),
+        OSGranularity::ID => id.to_string(),
+        OSGranularity::MajorVersion => match field("VERSION_ID") {
+            Some(version) => format!("{id} {}", version.split('.').next().unwrap_or(version)),
+            None => id.to_string(),
+        },
+        OSGranularity::Full => match field("PRETTY_NAME") {
+            Some(name) => name.to_string(),
+            None => format!("{id} {}", field("VERSION_ID").unwrap_or_default())
+                .trim()
+                .to_string(),
+        },
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_os_release(granularity: &OSGranularity) -> Result<String, HWIDError> {
+    let files = OS_RELEASE_FILES
+        .iter()
+        .map(Path::new)
+        .collect::<Vec<&Path>>();
+    get_os_release_from(&files, granularity)
+}
+
+#[cfg(target_os = "linux")]
+fn get_hostname_from(root: &Path, options: &HostnameOptions) -> Result<String, HWIDError> {
+    let hostname = match options.source {
+        HostnameSource::Static => get_file_content(root.join(STATIC_HOSTNAME_FILE))?
+            .lines()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string()),
+        HostnameSource::Pretty => parse_env_file(&get_file_content(root.join(MACHINE_INFO_FILE))?)
+            .into_iter()
+            .find(|(key, _)| key == "PRETTY_HOSTNAME")
+            .map(|(_, value)| value),
+        HostnameSource::Kernel => Some(
+            get_file_content(root.join(KERNEL_HOSTNAME_FILE.trim_start_matches('/')))?
+                .trim()
+                .to_string(),
+        ),
+    };
+    let mut hostname = hostname
+        .filter(|name| !name.is_empty())
+        .ok_or(HWIDError::new(
+            "HostName",
+            &format!("Could not retrieve the {:?} hostname", options.source),
+        ))?;
+    // The pretty hostname is free-form text, where dots aren't domains
+    if options.strip_domain && options.source != HostnameSource::Pretty {
+        hostname = hostname.split('.').next().unwrap_or_default().to_string();
+    }
+    if options.lowercase {
+        hostname = hostname.to_lowercase();
+    }
+    Ok(hostname)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_hostname(options: &HostnameOptions) -> Result<String, HWIDError> {
+    get_hostname_from(Path::new("/"), options)
+}
+
+/// Whether `path` itself is a mount point, which for a file means it was bind-mounted over.
+#[cfg(target_os = "linux")]
+fn is_mount_point(mounts: &[MountInfo], path: &Path) -> bool {
+    find_mount(mounts, path).is_some_and(|mount| mount.mount_point == path)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn is_machine_id_bind_mounted() -> Result<bool, HWIDError> {
+    let file = match get_machine_id_file() {
+        Some(file) => fs::canonicalize(file)?,
+        None => return Ok(false),
+    };
+    Ok(is_mount_point(&get_mountinfo()?, &file))
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct EthtoolPermAddr {
+    cmd: u32,
+    size: u32,
+    data: [u8; MAX_ADDR_LEN],
+}
+
+/// `struct ifreq` with `ifr_data` set, padded to the size of its largest union member.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct EthtoolRequest {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    data: *mut EthtoolPermAddr,
+    padding: [u8; 24],
+}
+
+/// Reads the permanent MAC address of an interface with the `ETHTOOL_GPERMADDR` ioctl,
+/// formatted like `/sys/class/net/<iface>/address`.
+/// Returns `None` when the driver doesn't report one.
+#[cfg(target_os = "linux")]
+fn get_permanent_mac(interface: &str) -> Option<String> {
+    if interface.len() >= libc::IFNAMSIZ {
+        return None;
+    }
+    let mut perm_addr = EthtoolPermAddr {
+        cmd: ETHTOOL_GPERMADDR,
+        size: MAX_ADDR_LEN as u32,
+        data: [0; MAX_ADDR_LEN],
+    };
+    let mut request = EthtoolRequest {
+        name: [0; libc::IFNAMSIZ],
+        data: &mut perm_addr,
+        padding: [0; 24],
+    };
+    for (dst, src) in request.name.iter_mut().zip(interface.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    let status = unsafe {
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if socket < 0 {
+            return None;
+        }
+        let status = libc::ioctl(socket, SIOCETHTOOL as _, &mut request);
+        libc::close(socket);
+        status
+    };
+    let size = (perm_addr.size as usize).min(MAX_ADDR_LEN);
+    let address = &perm_addr.data[..size];
+    if status != 0 || address.iter().all(|byte| *byte == 0) {
+        return None;
+    }
+    Some(
+        address
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<String>>()
+            .join(":"),
+    )
+}
+
+/// Lists the hardware network interfaces whose MAC address differs from their permanent one,
+/// given by `permanent_mac`. Interfaces without a known permanent address are skipped.
+#[cfg(target_os = "linux")]
+fn get_changed_mac_interfaces_from(
+    net_dir: &Path,
+    permanent_mac: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, HWIDError> {
+    let mut interfaces = vec![];
+    for entry in fs::read_dir(net_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        // Virtual interfaces like bridges and veths get their address set on purpose
+        if !path.join("device").exists() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        let address = match get_file_content(path.join("address")) {
+            Ok(address) => address.trim().to_lowercase(),
+            Err(_) => continue,
+        };
+        if let Some(permanent) = permanent_mac(&name) {
+            if !permanent.eq_ignore_ascii_case(&address) {
+                interfaces.push(name);
+            }
+        }
+    }
+    interfaces.sort();
+    Ok(interfaces)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_changed_mac_interfaces() -> Result<Vec<String>, HWIDError> {
+    get_changed_mac_interfaces_from(Path::new(NET_SYSFS_DIR), &get_permanent_mac)
+}
+
+/// Lists the `(field, value)` DMI fields holding a placeholder instead of a real value.
+#[cfg(target_os = "linux")]
+fn get_placeholder_dmi_from(dmi_dir: &Path) -> Vec<(String, String)> {
+    DMI_ID_FIELDS
+        .iter()
+        // Serials and the UUID can only be read by root
+        .filter_map(|field| {
+            let value = get_file_content(dmi_dir.join(field)).ok()?;
+            Some((field.to_string(), value.trim().to_string()))
+        })
+        .filter(|(_, value)| {
+            DMI_PLACEHOLDERS
+                .iter()
+                .any(|placeholder| placeholder.eq_ignore_ascii_case(value))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn get_placeholder_dmi() -> Vec<(String, String)> {
+    get_placeholder_dmi_from(Path::new(DMI_ID_DIR))
+}
+
+#[cfg(target_os = "linux")]
+fn get_cloud_instance_id_from(root: &Path) -> Result<String, HWIDError> {
+    let sys_vendor = get_file_content(root.join("sys/class/dmi/id/sys_vendor")).unwrap_or_default();
+    let provider_ids = CLOUD_INSTANCE_ID_FILES
+        .iter()
+        .filter(|(vendor, _, _)| vendor.is_empty() || sys_vendor.trim() == *vendor)
+        .filter_map(|(_, file, prefix)| {
+            let id = get_file_content(root.join(file)).ok()?.trim().to_string();
+            (id.len() > prefix.len() && id.starts_with(prefix)).then_some(id)
+        });
+    let cloud_init_id = get_file_content(root.join(CLOUD_INIT_INSTANCE_ID_FILE))
+        .ok()
+        .map(|id| id.trim().to_string());
+    provider_ids
+        .chain(cloud_init_id)
+        .find(|id| !id.is_empty() && !CLOUD_SHARED_IDS.contains(&id.as_str()))
+        .ok_or(HWIDError::new(
+            "CloudInstanceID",
+            "Could not find the instance ID in the hypervisor, DMI or cloud-init files",
+        ))
+}
+
+/// Reads the cloud instance ID from the files of the hypervisor, DMI and cloud-init.
+#[cfg(target_os = "linux")]
+pub(crate) fn get_cloud_instance_id() -> Result<String, HWIDError> {
+    get_cloud_instance_id_from(Path::new("/"))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+    use crate::utils::fixture_dir;
+
+    fn cpu_fixture() -> Vec<(String, String)> {
+        let mut files = vec![("online".to_string(), "0-3\n".to_string())];
+        for cpu in 0..4 {
+            let dir = format!("cpu{cpu}");
+            files.push((format!("{dir}/topology/physical_package_id"), "0\n".into()));
+            files.push((format!("{dir}/topology/core_id"), format!("{}\n", cpu / 2)));
+            files.push((format!("{dir}/cache/index0/level"), "1\n".into()));
+            files.push((format!("{dir}/cache/index0/type"), "Data\n".into()));
+            files.push((format!("{dir}/cache/index0/size"), "32K\n".into()));
+            files.push((
+                format!("{dir}/cache/index0/shared_cpu_list"),
+                format!("{cpu}\n"),
+            ));
+            files.push((format!("{dir}/cache/index2/level"), "2\n".into()));
+            files.push((format!("{dir}/cache/index2/type"), "Unified\n".into()));
+            files.push((format!("{dir}/cache/index2/size"), "512K\n".into()));
+            let pair = cpu / 2 * 2;
+            files.push((
+                format!("{dir}/cache/index2/shared_cpu_list"),
+                format!("{}-{}\n", pair, pair + 1),
+            ));
+            files.push((format!("{dir}/cache/index3/level"), "3\n".into()));
+            files.push((format!("{dir}/cache/index3/type"), "Unified\n".into()));
+            files.push((format!("{dir}/cache/index3/size"), "8M\n".into()));
+            files.push((
+                format!("{dir}/cache/index3/shared_cpu_list"),
+                "0-3\n".into(),
+            ));
+        }
+        files
+    }
+
+    #[test]
+    fn cpu_topology_from_sysfs() {
+        let files = cpu_fixture();
+        let root = fixture_dir(&files);
+        let topology = get_cpu_topology_from(&root).unwrap();
+        assert_eq!(topology.sockets, 1);
+        assert_eq!(topology.cores, 2);
+        assert_eq!(topology.threads, 4);
+        assert_eq!(topology.l2_cache_kb, 1024);
+        assert_eq!(topology.l3_cache_kb, 8192);
+    }
+
+    #[test]
+    fn cpu_topology_missing_sysfs_is_an_error() {
+        let root = fixture_dir::<&str>(&[]);
+        assert!(get_cpu_topology_from(&root).is_err());
+    }
+
+    #[test]
+    fn cpu_list_ranges() {
+        assert_eq!(
+            parse_cpu_list("0-2,5,7-8\n").unwrap(),
+            vec![0, 1, 2, 5, 7, 8]
+        );
+        assert!(parse_cpu_list("0-a").is_err());
+    }
+
+    fn memory_device(size: u16, extended: u32) -> Vec<u8> {
+        let mut raw = vec![0u8; 0x28];
+        raw[0] = 17;
+        raw[1] = 0x28;
+        raw[0x0C..0x0E].copy_from_slice(&size.to_le_bytes());
+        raw[0x1C..0x20].copy_from_slice(&extended.to_le_bytes());
+        raw
+    }
+
+    #[test]
+    fn installed_memory_from_smbios() {
+        let root = fixture_dir::<&str>(&[]);
+        for (name, raw) in [
+            ("17-0", memory_device(8192, 0)),
+            ("17-1", memory_device(0x7FFF, 65536)),
+            ("17-2", memory_device(0, 0)),
+            ("17-3", memory_device(0x8000 | 2048, 0)),
+        ] {
+            fs::create_dir_all(root.join(name)).unwrap();
+            fs::write(root.join(name).join("raw"), raw).unwrap();
+        }
+        let memory =
+            get_installed_memory_from(&root, &root.join("meminfo"), &MemorySource::SMBIOS, 1024);
+        assert_eq!(memory.unwrap(), "74752");
+    }
+
+    #[test]
+    fn installed_memory_from_meminfo() {
+        let root = fixture_dir(&[("meminfo", "MemTotal:       16279548 kB\nMemFree: 1 kB\n")]);
+        let dmi = root.join("dmi");
+        let meminfo = root.join("meminfo");
+        let memory = get_installed_memory_from(&dmi, &meminfo, &MemorySource::MemTotal, 1024);
+        assert_eq!(memory.unwrap(), "16384");
+        let memory = get_installed_memory_from(&dmi, &meminfo, &MemorySource::MemTotal, 0);
+        assert_eq!(memory.unwrap(), "15897");
+        // Unreadable SMBIOS entries are an error rather than a silent switch to meminfo
+        assert!(get_installed_memory_from(&dmi, &meminfo, &MemorySource::SMBIOS, 1024).is_err());
+    }
+
+    #[test]
+    fn raspberry_pi_serials() {
+        let root = fixture_dir(&[
+            ("proc/device-tree/serial-number", "10000000a1b2c3d4\0"),
+            (
+                "proc/device-tree/compatible",
+                "raspberrypi,4-model-b\0brcm,bcm2711\0",
+            ),
+            (
+                "proc/cpuinfo",
+                "processor\t: 0\nHardware\t: BCM2835\nSerial\t\t: 10000000a1b2c3d4\nModel\t\t: Raspberry Pi 4\n",
+            ),
+        ]);
+        assert_eq!(get_soc_serial_from(&root).unwrap(), "10000000a1b2c3d4");
+        assert_eq!(get_cpuinfo_serial_from(&root).unwrap(), "10000000a1b2c3d4");
+        assert_eq!(
+            get_device_tree_strings(&root, "compatible").unwrap(),
+            vec!["raspberrypi,4-model-b", "brcm,bcm2711"]
+        );
+    }
+
+    #[test]
+    fn zero_cpuinfo_serial_is_an_error() {
+        let root = fixture_dir(&[("proc/cpuinfo", "Serial\t\t: 0000000000000000\n")]);
+        assert!(get_cpuinfo_serial_from(&root).is_err());
+        assert!(get_soc_serial_from(&root).is_err());
+    }
+
+    fn pci_device(slot: &str, class: &str, ids: [&str; 4]) -> Vec<(String, String)> {
+        let dir = format!("devices/{slot}");
+        vec![
+            (format!("{dir}/class"), format!("{class}\n")),
+            (format!("{dir}/vendor"), format!("{}\n", ids[0])),
+            (format!("{dir}/device"), format!("{}\n", ids[1])),
+            (format!("{dir}/subsystem_vendor"), format!("{}\n", ids[2])),
+            (format!("{dir}/subsystem_device"), format!("{}\n", ids[3])),
+        ]
+    }
+
+    #[test]
+    fn pci_inventory_skips_hotplug_and_excluded_classes() {
+        let host_bridge = pci_device(
+            "0000:00:00.0",
+            "0x060000",
+            ["0x8086", "0x1237", "0x1af4", "0x1100"],
+        );
+        let vga = pci_device(
+            "0000:00:02.0",
+            "0x030000",
+            ["0x1234", "0x1111", "0x1af4", "0x1100"],
+        );
+        let nic = pci_device(
+            "0000:00:03.0",
+            "0x020000",
+            ["0x1af4", "0x1000", "0x1af4", "0x0001"],
+        );
+        let nvme = pci_device(
+            "0000:01:00.0",
+            "0x010802",
+            ["0x144d", "0xa808", "0x144d", "0xa801"],
+        );
+        let base = [host_bridge.clone(), vga.clone()].concat();
+        let slots = vec![("slots/1/address".to_string(), "0000:01:00\n".to_string())];
+
+        let expected = get_pci_devices_from(&fixture_dir(&base), &[]).unwrap();
+        let root = fixture_dir(&[base.clone(), nic, nvme, slots].concat());
+        assert_eq!(get_pci_devices_from(&root, &[0x02]).unwrap(), expected);
+        assert_ne!(get_pci_devices_from(&root, &[]).unwrap(), expected);
+
+        let root = fixture_dir(&[vga, host_bridge].concat());
+        assert_eq!(get_pci_devices_from(&root, &[]).unwrap(), expected);
+    }
+
+    #[test]
+    fn connected_monitor_edid() {
+        let root = fixture_dir(&[
+            ("card0-DP-1/status", "connected\n"),
+            ("card0-HDMI-A-1/status", "disconnected\n"),
+            ("card0-HDMI-A-1/edid", ""),
+            ("card0-eDP-1/status", "connected\n"),
+            ("card0-eDP-1/edid", ""),
+            ("card0-DP-2/status", "connected\n"),
+        ]);
+        fs::write(root.join("card0-DP-1/edid"), crate::edid::DELL_U2719D).unwrap();
+        // Truncated behind a dock
+        fs::write(
+            root.join("card0-DP-2/edid"),
+            &crate::edid::DELL_U2719D[..100],
+        )
+        .unwrap();
+        assert_eq!(
+            get_monitor_edid_from(&root).unwrap(),
+            "DEL:a0f1:4c4d3641:7MT0186L0B5S"
+        );
+        fs::remove_file(root.join("card0-DP-1/edid")).unwrap();
+        assert!(get_monitor_edid_from(&root).is_err());
+        fs::remove_file(root.join("card0-DP-2/edid")).unwrap();
+        assert!(get_monitor_edid_from(&root).is_err());
+    }
+
+    const LSBLK_OUTPUT: &str = r#"{
+        "blockdevices": [
+            {"name": "nvme0n1", "maj:min": "259:0", "mountpoint": null, "uuid": null, "label": null,
+                "children": [
+                    {"name": "nvme0n1p1", "maj:min": "259:1", "mountpoint": "/boot/efi", "uuid": "B1C2-D3E4", "label": null},
+                    {"name": "nvme0n1p2", "maj:min": "259:2", "mountpoint": "/", "uuid": "2f1c7a52-5d1e-4b8a-9d2c-6f1e3b0c9a11", "label": null}
+                ]
+            },
+            {"name": "sdb", "maj:min": "8:16", "mountpoint": null, "uuid": null, "label": null,
+                "children": [
+                    {"name": "sdb1", "maj:min": "8:17", "mountpoint": "/media/license", "uuid": "6A1B-2C3D", "label": "LICENSE"}
+                ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn usb_volume_by_mountpoint_and_label() {
+        let root = fixture_dir(&[
+            (
+                "devices/pci0000:00/0000:00:14.0/usb2/2-1/idVendor",
+                "0781\n",
+            ),
+            (
+                "devices/pci0000:00/0000:00:14.0/usb2/2-1/idProduct",
+                "5581\n",
+            ),
+            (
+                "devices/pci0000:00/0000:00:14.0/usb2/2-1/serial",
+                "4C530001230\n",
+            ),
+            (
+                "devices/pci0000:00/0000:00:14.0/usb2/2-1/2-1:1.0/host3/target3:0:0/3:0:0:0/vendor",
+                "SanDisk\n",
+            ),
+            (
+                "devices/pci0000:00/0000:01:00.0/nvme/nvme0/model",
+                "Samsung\n",
+            ),
+        ]);
+        fs::create_dir_all(root.join("block/sdb")).unwrap();
+        fs::create_dir_all(root.join("block/nvme0n1")).unwrap();
+        std::os::unix::fs::symlink(
+            root.join("devices/pci0000:00/0000:00:14.0/usb2/2-1/2-1:1.0/host3/target3:0:0/3:0:0:0"),
+            root.join("block/sdb/device"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(
+            root.join("devices/pci0000:00/0000:01:00.0/nvme/nvme0"),
+            root.join("block/nvme0n1/device"),
+        )
+        .unwrap();
+
+        let devices: Output = serde_json::from_str(LSBLK_OUTPUT).unwrap();
+        let block_dir = root.join("block");
+        let expected = "0781:5581:4C530001230:6A1B-2C3D";
+        let volume = Volume::MountPoint("/media/license".into());
+        assert_eq!(
+            get_usb_volume_id_from(&block_dir, &devices, &volume).unwrap(),
+            expected
+        );
+        let volume = Volume::Label("LICENSE".into());
+        assert_eq!(
+            get_usb_volume_id_from(&block_dir, &devices, &volume).unwrap(),
+            expected
+        );
+        let volume = Volume::MountPoint("/".into());
+        assert!(get_usb_volume_id_from(&block_dir, &devices, &volume).is_err());
+        let volume = Volume::Label("MISSING".into());
+        assert!(get_usb_volume_id_from(&block_dir, &devices, &volume).is_err());
+    }
+
+    const MOUNTINFO: &str = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+23 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+24 22 259:1 / /boot/efi rw,relatime shared:2 - vfat /dev/nvme0n1p1 rw
+25 22 179:2 / /opt/my\\040app rw,relatime shared:3 - ext4 /dev/mmcblk0p2 rw
+26 22 8:17 / /media/license rw,relatime shared:4 - vfat /dev/sdb1 rw
+";
+
+    #[test]
+    fn mountinfo_parsing() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(mounts.len(), 5);
+        assert_eq!(mounts[3].mount_point, Path::new("/opt/my app"));
+
+        let mount = find_mount(&mounts, Path::new("/boot/efi/EFI/BOOT")).unwrap();
+        assert_eq!(mount.mount_point, Path::new("/boot/efi"));
+        let mount = find_mount(&mounts, Path::new("/bootstrap")).unwrap();
+        assert_eq!(mount.mount_point, Path::new("/"));
+    }
+
+    #[test]
+    fn disk_id_for_any_path() {
+        let root = fixture_dir(&[("mmcblk0/device/cid", "9f544930303030300000a1b2c3d40111\n")]);
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let devices: Output = serde_json::from_str(
+            &LSBLK_OUTPUT.replace(
+                r#"{"name": "sdb""#,
+                r#"{"name": "mmcblk0", "maj:min": "179:0", "children": [{"name": "mmcblk0p2", "maj:min": "179:2", "mountpoint": "/opt/my app", "uuid": "0c5a1b2e-0000-4000-8000-000000000001"}]},
+                {"name": "sdb""#,
+            ),
+        )
+        .unwrap();
+
+        let id = |path: &str| get_disk_id_at_from(&root, &mounts, &devices, Path::new(path));
+        assert_eq!(
+            id("/etc/hostname").unwrap(),
+            "2f1c7a52-5d1e-4b8a-9d2c-6f1e3b0c9a11"
+        );
+        assert_eq!(id("/media/license/key").unwrap(), "6A1B-2C3D");
+        assert_eq!(
+            id("/opt/my app/bin").unwrap(),
+            "9f544930303030300000a1b2c3d40111"
+        );
+        assert!(id("/proc/self").is_err());
+    }
+
+    #[test]
+    fn disk_id_for_subvolumes_and_bind_mounts() {
+        let root = fixture_dir::<&str>(&[]);
+        // Fedora's default layout, plus /opt bound from /data on a second disk
+        let mounts = parse_mountinfo(
+            "\
+60 1 0:35 /root / rw,relatime shared:1 - btrfs /dev/nvme0n1p3 rw,subvol=/root
+61 60 0:35 /home /home rw,relatime shared:2 - btrfs /dev/nvme0n1p3 rw,subvol=/home
+62 60 8:17 / /data rw,relatime shared:3 - ext4 /dev/sdb1 rw
+63 60 8:17 /opt /opt rw,relatime shared:3 - ext4 /dev/sdb1 rw
+",
+        );
+        let devices: Output = serde_json::from_str(
+            r#"{"blockdevices": [
+                {"name": "nvme0n1", "maj:min": "259:0", "mountpoint": null, "uuid": null,
+                    "children": [
+                        {"name": "nvme0n1p3", "maj:min": "259:3", "mountpoint": "/home", "uuid": "8e0b5c2a-7d41-4f3e-9a6b-2c1d0e9f8a7b"}
+                    ]
+                },
+                {"name": "sdb", "maj:min": "8:16", "mountpoint": null, "uuid": null,
+                    "children": [
+                        {"name": "sdb1", "maj:min": "8:17", "mountpoint": "/data", "uuid": "3c9d7e1f-0a2b-4c5d-8e6f-7a8b9c0d1e2f"}
+                    ]
+                }
+            ]}"#,
+        )
+        .unwrap();
+
+        let id = |path: &str| get_disk_id_at_from(&root, &mounts, &devices, Path::new(path));
+        assert_eq!(
+            id("/etc/hostname").unwrap(),
+            "8e0b5c2a-7d41-4f3e-9a6b-2c1d0e9f8a7b"
+        );
+        assert_eq!(
+            id("/home/alice").unwrap(),
+            "8e0b5c2a-7d41-4f3e-9a6b-2c1d0e9f8a7b"
+        );
+        assert_eq!(
+            id("/opt/app").unwrap(),
+            "3c9d7e1f-0a2b-4c5d-8e6f-7a8b9c0d1e2f"
+        );
+    }
+
+    #[test]
+    fn disk_id_without_lsblk() {
+        let root = fixture_dir(&[(
+            "sys/dev/block/259:2/uevent",
+            "MAJOR=259\nMINOR=2\nDEVNAME=nvme0n1p2\nDEVTYPE=partition\n",
+        )]);
+        let mut image = vec![0u8; 2048];
+        image[1024 + 0x38..1024 + 0x3A].copy_from_slice(&[0x53, 0xEF]);
+        image[1024 + 0x68..1024 + 0x78].copy_from_slice(&[0xAB; 16]);
+        fs::create_dir_all(root.join("dev")).unwrap();
+        fs::write(root.join("dev/nvme0n1p2"), image).unwrap();
+        let mounts = parse_mountinfo(MOUNTINFO);
+
+        assert_eq!(
+            get_disk_id_without_lsblk_from(&root, &mounts).unwrap(),
+            "abababab-abab-abab-abab-abababababab"
+        );
+
+        fs::create_dir_all(root.join("dev/disk/by-uuid")).unwrap();
+        std::os::unix::fs::symlink(
+            "../../nvme0n1p2",
+            root.join("dev/disk/by-uuid/2f1c7a52-5d1e-4b8a-9d2c-6f1e3b0c9a11"),
+        )
+        .unwrap();
+        assert_eq!(
+            get_disk_id_without_lsblk_from(&root, &mounts).unwrap(),
+            "2f1c7a52-5d1e-4b8a-9d2c-6f1e3b0c9a11"
+        );
+    }
+
+    #[test]
+    fn mmc_root_with_and_without_lsblk() {
+        let uuid = "0c5a1b2e-0000-4000-8000-000000000001";
+        let root = fixture_dir(&[
+            (
+                "sys/block/mmcblk0/device/cid",
+                "9f544930303030300000a1b2c3d40111\n",
+            ),
+            (
+                "sys/dev/block/179:2/uevent",
+                "MAJOR=179\nMINOR=2\nDEVNAME=mmcblk0p2\nDEVTYPE=partition\n",
+            ),
+            ("dev/mmcblk0p2", ""),
+        ]);
+        fs::create_dir_all(root.join("dev/disk/by-uuid")).unwrap();
+        std::os::unix::fs::symlink("../../mmcblk0p2", root.join("dev/disk/by-uuid").join(uuid))
+            .unwrap();
+        let devices: Output = serde_json::from_str(
+            r#"{"blockdevices": [
+                {"name": "mmcblk0", "maj:min": "179:0", "mountpoint": null, "uuid": null,
+                    "children": [
+                        {"name": "mmcblk0p1", "maj:min": "179:1", "mountpoint": "/boot", "uuid": "5DE4-665C"},
+                        {"name": "mmcblk0p2", "maj:min": "179:2", "mountpoint": "/", "uuid": "0c5a1b2e-0000-4000-8000-000000000001"}
+                    ]
+                }
+            ]}"#,
+        )
+        .unwrap();
+        let mounts =
+            parse_mountinfo("22 1 179:2 / / rw,relatime shared:1 - ext4 /dev/mmcblk0p2 rw\n");
+
+        // The card CID is ignored, so that the ID of sdcard-rooted machines stays the partition UUID
+        assert_eq!(devices.get_root().unwrap(), uuid);
+        assert_eq!(
+            get_disk_id_without_lsblk_from(&root, &mounts).unwrap(),
+            uuid
+        );
+    }
+
+    #[test]
+    fn root_disk_gpt() {
+        let partition = "devices/pci0000:00/0000:00:04.0/nvme/nvme0/nvme0n1/nvme0n1p2";
+        let root = fixture_dir(&[
+            (
+                "sys/devices/pci0000:00/0000:00:04.0/nvme/nvme0/nvme0n1/uevent".to_string(),
+                "MAJOR=259\nMINOR=0\nDEVNAME=nvme0n1\nDEVTYPE=disk\n".to_string(),
+            ),
+            (
+                format!("sys/{partition}/uevent"),
+                "MAJOR=259\nMINOR=2\nDEVNAME=nvme0n1p2\nDEVTYPE=partition\nPARTN=2\n".to_string(),
+            ),
+        ]);
+        fs::create_dir_all(root.join("sys/dev/block")).unwrap();
+        std::os::unix::fs::symlink(
+            format!("../../{partition}"),
+            root.join("sys/dev/block/259:2"),
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("dev")).unwrap();
+        fs::write(
+            root.join("dev/nvme0n1"),
+            crate::gpt::gpt_image(
+                512,
+                "5b3e2a9c-1f4d-4e8b-a2c6-9d0e7f1a3b5c",
+                &[
+                    (
+                        crate::gpt::ESP_TYPE_GUID,
+                        "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d",
+                    ),
+                    (
+                        "0fc63daf-8483-4772-8e79-3d69d8477de4",
+                        "f0e1d2c3-b4a5-4968-8776-655443322110",
+                    ),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let (gpt, number) = get_root_gpt_from(&root, &mounts).unwrap();
+        assert_eq!(number, 2);
+        assert_eq!(gpt.disk_guid, "5b3e2a9c-1f4d-4e8b-a2c6-9d0e7f1a3b5c");
+        assert_eq!(
+            gpt.partition(number).unwrap().unique_guid,
+            "f0e1d2c3-b4a5-4968-8776-655443322110"
+        );
+    }
+
+    #[test]
+    fn efi_state() {
+        let loader_uuid = "0A1B2C3D-4E5F-4A6B-8C7D-9E0F1A2B3C4D\0"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let root = fixture_dir::<&str>(&[]);
+        let efivars = root.join("efi/efivars");
+        fs::create_dir_all(&efivars).unwrap();
+        for (name, value) in [
+            (format!("SecureBoot-{EFI_GLOBAL_GUID}"), vec![1]),
+            (format!("SetupMode-{EFI_GLOBAL_GUID}"), vec![0]),
+            (
+                format!("LoaderDevicePartUUID-{EFI_LOADER_GUID}"),
+                loader_uuid,
+            ),
+        ] {
+            // Every variable starts with 4 bytes of attributes
+            fs::write(efivars.join(name), [vec![7, 0, 0, 0], value].concat()).unwrap();
+        }
+
+        let efi_dir = root.join("efi");
+        assert_eq!(get_boot_mode_from(&efi_dir), "uefi");
+        assert_eq!(get_boot_mode_from(&root.join("bios")), "bios");
+        assert_eq!(get_efi_flag_from(&efi_dir, "SecureBoot").unwrap(), "1");
+        assert_eq!(get_efi_flag_from(&efi_dir, "SetupMode").unwrap(), "0");
+        assert!(get_efi_flag_from(&efi_dir, "AuditMode").is_err());
+        assert_eq!(
+            get_loader_partuuid_from(&efi_dir).unwrap(),
+            "0a1b2c3d-4e5f-4a6b-8c7d-9e0f1a2b3c4d"
+        );
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn hostid_like_glibc() {
+        let root = fixture_dir(&[("hostid", "\u{7f}\0\u{1}\0")]);
+        assert_eq!(
+            get_hostid_from(&root.join("hostid"), "localhost"),
+            "0001007f"
+        );
+        assert_eq!(
+            get_hostid_from(&root.join("missing"), "127.0.1.1"),
+            "007f0101"
+        );
+        assert_eq!(
+            get_hostid_from(&root.join("missing"), "10.20.30.40"),
+            "140a281e"
+        );
+    }
+
+    #[test]
+    fn os_release_granularity() {
+        let root = fixture_dir(&[(
+            "os-release",
+            "PRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\nNAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\n\
+             VERSION=\"22.04.3 LTS (Jammy Jellyfish)\"\nID=ubuntu\nID_LIKE=debian\n",
+        )]);
+        let missing = root.join("missing");
+        let files = [missing.as_path(), &root.join("os-release")];
+        let release = |granularity| get_os_release_from(&files, &granularity).unwrap();
+        assert_eq!(release(OSGranularity::Family), "debian");
+        assert_eq!(release(OSGranularity::ID), "ubuntu");
+        assert_eq!(release(OSGranularity::MajorVersion), "ubuntu 22");
+        assert_eq!(release(OSGranularity::Full), "Ubuntu 22.04.3 LTS");
+        assert!(get_os_release_from(&[missing.as_path()], &OSGranularity::ID).is_err());
+    }
+
+    #[test]
+    fn os_release_defaults_and_quoting() {
+        let fields = parse_env_file(
+            "# comment\nNAME='Arch Linux'\nBUILD_ID=rolling\nVARIANT=\"say \\\"hi\\\"\"\n",
+        );
+        assert_eq!(fields[0], ("NAME".to_string(), "Arch Linux".to_string()));
+        assert_eq!(fields[2], ("VARIANT".to_string(), "say \"hi\"".to_string()));
+
+        let root = fixture_dir(&[("os-release", "NAME=Linux\n")]);
+        let path = root.join("os-release");
+        let files = [path.as_path()];
+        assert_eq!(
+            get_os_release_from(&files, &OSGranularity::Family).unwrap(),
+            "linux"
+        );
+        assert_eq!(
+            get_os_release_from(&files, &OSGranularity::MajorVersion).unwrap(),
+            "linux"
+        );
+    }
+
+    #[test]
+    fn hostname_sources() {
+        let root = fixture_dir(&[
+            (
+                "etc/hostname",
+                "# set by the installer\nBuild-Server.corp.example.com\n",
+            ),
+            (
+                "etc/machine-info",
+                "PRETTY_HOSTNAME=\"Build Server\"\nCHASSIS=server\n",
+            ),
+            ("proc/sys/kernel/hostname", "dhcp-10-0-0-12\n"),
+        ]);
+        let hostname = |source, lowercase, strip_domain| {
+            let options = HostnameOptions {
+                source,
+                lowercase,
+                strip_domain,
+            };
+            get_hostname_from(&root, &options).unwrap()
+        };
+        assert_eq!(
+            hostname(HostnameSource::Static, false, false),
+            "Build-Server.corp.example.com"
+        );
+        assert_eq!(hostname(HostnameSource::Static, true, true), "build-server");
+        assert_eq!(
+            hostname(HostnameSource::Pretty, false, false),
+            "Build Server"
+        );
+        assert_eq!(
+            hostname(HostnameSource::Kernel, false, true),
+            "dhcp-10-0-0-12"
+        );
+
+        fs::write(
+            root.join("etc/machine-info"),
+            "PRETTY_HOSTNAME=\"Dr. Who's PC\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            hostname(HostnameSource::Pretty, false, true),
+            "Dr. Who's PC"
+        );
+
+        fs::write(root.join("etc/machine-info"), "CHASSIS=server\n").unwrap();
+        let options = HostnameOptions {
+            source: HostnameSource::Pretty,
+            lowercase: false,
+            strip_domain: false,
+        };
+        assert!(get_hostname_from(&root, &options).is_err());
+    }
+
+    #[test]
+    fn machine_id_bind_mount() {
+        let mut mounts = parse_mountinfo(MOUNTINFO);
+        assert!(!is_mount_point(&mounts, Path::new("/etc/machine-id")));
+        mounts.extend(parse_mountinfo(
+            "99 22 0:45 /fake-id /etc/machine-id rw,relatime shared:1 - tmpfs tmpfs rw\n",
+        ));
+        assert!(is_mount_point(&mounts, Path::new("/etc/machine-id")));
+        assert!(!is_mount_point(&mounts, Path::new("/etc/hostname")));
+    }
+
+    #[test]
+    fn changed_mac_interfaces() {
+        let root = fixture_dir(&[
+            ("enp3s0/device/vendor", "0x8086\n"),
+            ("enp3s0/address", "02:42:ac:11:00:02\n"),
+            ("wlp2s0/device/vendor", "0x8086\n"),
+            ("wlp2s0/address", "3c:a9:f4:12:34:56\n"),
+            ("eno1/device/vendor", "0x14e4\n"),
+            ("eno1/address", "b8:ca:3a:00:11:22\n"),
+            ("docker0/address", "02:42:5e:8a:1b:2c\n"),
+        ]);
+        let permanent = |name: &str| match name {
+            "enp3s0" => Some("00:1B:21:AA:BB:CC".to_string()),
+            "wlp2s0" => Some("3c:a9:f4:12:34:56".to_string()),
+            "docker0" => Some("00:00:00:00:00:01".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            get_changed_mac_interfaces_from(&root, &permanent).unwrap(),
+            vec!["enp3s0".to_string()]
+        );
+    }
+
+    #[test]
+    fn placeholder_dmi() {
+        let root = fixture_dir(&[
+            ("sys_vendor", "ASRock\n"),
+            ("product_name", "To Be Filled By O.E.M.\n"),
+            ("board_name", "B450M Pro4\n"),
+            ("board_serial", "default string\n"),
+        ]);
+        assert_eq!(
+            get_placeholder_dmi_from(&root),
+            vec![
+                (
+                    "product_name".to_string(),
+                    "To Be Filled By O.E.M.".to_string()
+                ),
+                ("board_serial".to_string(), "default string".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn cloud_instance_id() {
+        let aws = fixture_dir(&[
+            ("sys/class/dmi/id/sys_vendor", "Amazon EC2\n"),
+            ("sys/class/dmi/id/board_asset_tag", "i-0a1b2c3d4e5f67890\n"),
+        ]);
+        assert_eq!(
+            get_cloud_instance_id_from(&aws).unwrap(),
+            "i-0a1b2c3d4e5f67890"
+        );
+        let azure = fixture_dir(&[
+            ("sys/class/dmi/id/board_asset_tag", "None\n"),
+            (
+                "sys/class/dmi/id/chassis_asset_tag",
+                "7783-7084-3265-9085-8269-3286-77\n",
+            ),
+            (
+                "var/lib/cloud/data/instance-id",
+                "5c3a9e1b-7d2f-4a6e-b8c4-1f0d9e2a3b4c\n",
+            ),
+        ]);
+        assert_eq!(
+            get_cloud_instance_id_from(&azure).unwrap(),
+            "5c3a9e1b-7d2f-4a6e-b8c4-1f0d9e2a3b4c"
+        );
+        let bare_metal = fixture_dir(&[
+            ("sys/class/dmi/id/board_asset_tag", "Default string\n"),
+            ("var/lib/cloud/data/instance-id", "iid-datasource-none\n"),
+        ]);
+        assert!(get_cloud_instance_id_from(&bare_metal).is_err());
+        let it_asset = fixture_dir(&[
+            ("sys/class/dmi/id/sys_vendor", "Dell Inc.\n"),
+            ("sys/class/dmi/id/board_asset_tag", "i-LAPTOP-0042\n"),
+            ("sys/class/dmi/id/chassis_asset_tag", "ACME-IT-004217\n"),
+        ]);
+        assert!(get_cloud_instance_id_from(&it_asset).is_err());
+    }
+}
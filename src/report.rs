@@ -0,0 +1,176 @@
+#[cfg(target_os = "linux")]
+use crate::linux::{
+    get_changed_mac_interfaces, get_machine_id_file, get_placeholder_dmi,
+    is_machine_id_bind_mounted,
+};
+#[cfg(target_os = "linux")]
+use std::env;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Machine ids that are shared by many installs, like the placeholders left in images:
+/// `uninitialized` is written by image builders for systemd to replace on first boot.
+/// Ids made of a single repeated digit, like all zeros or all `f`, are caught by [`default_machine_id`].
+const DEFAULT_MACHINE_IDS: [&str; 1] = ["uninitialized"];
+
+/// A hint that the machine is a clone of another one, i.e. a VM started from a golden image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloneSignal {
+    /// The machine id was last written before the root filesystem was created,
+    /// so it was copied from another install
+    MachineIdPredatesRootFs,
+    /// The machine id is a known default value
+    DefaultMachineId(String),
+    /// The token at this path was bound to other hardware, see [`IdBuilder::add_token_binding`](crate::IdBuilder::add_token_binding)
+    FileTokenMoved(PathBuf),
+}
+
+/// A hint that an identity source was spoofed.
+///
+/// These are only checked on Linux.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TamperSignal {
+    /// The machine id file is bind-mounted over, i.e. with a fake one
+    MachineIdBindMount,
+    /// The MAC address of this network interface was changed, i.e. with `ip link set address`
+    MacAddressChanged(String),
+    /// `LD_PRELOAD` is set, which can intercept the reads of every identity source
+    LdPreload(String),
+    /// A DMI field holds a placeholder value like `To Be Filled By O.E.M.`
+    PlaceholderDMI { field: String, value: String },
+}
+
+/// The ID returned by [`IdBuilder::build_with_report`](crate::IdBuilder::build_with_report), along with what was noticed while collecting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HWIDReport {
+    /// The hashed ID, as returned by [`IdBuilder::build`](crate::IdBuilder::build)
+    pub id: String,
+    /// Hints that the machine is a clone; your server may want to force a re-activation
+    pub clone_signals: Vec<CloneSignal>,
+    /// Hints that some identity sources were spoofed
+    pub tamper_signals: Vec<TamperSignal>,
+}
+
+/// Compares the modification time of the machine id, which survives copies, to the birth time of the root filesystem.
+#[cfg(target_os = "linux")]
+fn machine_id_predates_root(machine_id: &Path, root: &Path) -> bool {
+    let written = fs::metadata(machine_id).and_then(|m| m.modified());
+    let created = fs::metadata(root).and_then(|m| m.created());
+    match (written, created) {
+        (Ok(written), Ok(created)) => written < created,
+        _ => false,
+    }
+}
+
+fn default_machine_id(machine_id: &str, known_machine_ids: &[String]) -> Option<String> {
+    let machine_id = machine_id.trim();
+    let mut digits = machine_id.chars();
+    let repeated = digits
+        .next()
+        .is_some_and(|first| digits.all(|digit| digit.eq_ignore_ascii_case(&first)));
+    let is_default = repeated
+        || DEFAULT_MACHINE_IDS
+            .iter()
+            .any(|id| id.eq_ignore_ascii_case(machine_id))
+        || known_machine_ids
+            .iter()
+            .any(|id| id.trim().eq_ignore_ascii_case(machine_id));
+    is_default.then(|| machine_id.to_string())
+}
+
+pub(crate) fn detect_clone_signals(
+    machine_id: Option<&str>,
+    known_machine_ids: &[String],
+) -> Vec<CloneSignal> {
+    let mut signals = vec![];
+    #[cfg(target_os = "linux")]
+    if get_machine_id_file().is_some_and(|file| machine_id_predates_root(file, Path::new("/"))) {
+        signals.push(CloneSignal::MachineIdPredatesRootFs);
+    }
+    if let Some(id) = machine_id.and_then(|id| default_machine_id(id, known_machine_ids)) {
+        signals.push(CloneSignal::DefaultMachineId(id));
+    }
+    signals
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn detect_tamper_signals() -> Vec<TamperSignal> {
+    let mut signals = vec![];
+    if is_machine_id_bind_mounted().unwrap_or(false) {
+        signals.push(TamperSignal::MachineIdBindMount);
+    }
+    for interface in get_changed_mac_interfaces().unwrap_or_default() {
+        signals.push(TamperSignal::MacAddressChanged(interface));
+    }
+    if let Some(preload) = env::var_os("LD_PRELOAD").filter(|preload| !preload.is_empty()) {
+        signals.push(TamperSignal::LdPreload(
+            preload.to_string_lossy().to_string(),
+        ));
+    }
+    for (field, value) in get_placeholder_dmi() {
+        signals.push(TamperSignal::PlaceholderDMI { field, value });
+    }
+    signals
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn detect_tamper_signals() -> Vec<TamperSignal> {
+    vec![]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_machine_ids() {
+        let known = vec!["4a1b7c3e9f2d4e8a8b6c5d4e3f2a1b0c".to_string()];
+        assert_eq!(
+            default_machine_id("00000000000000000000000000000000\n", &known),
+            Some("00000000000000000000000000000000".to_string())
+        );
+        assert_eq!(
+            default_machine_id("4A1B7C3E9F2D4E8A8B6C5D4E3F2A1B0C", &known),
+            Some("4A1B7C3E9F2D4E8A8B6C5D4E3F2A1B0C".to_string())
+        );
+        assert_eq!(
+            default_machine_id("11111111111111111111111111111111", &[]),
+            Some("11111111111111111111111111111111".to_string())
+        );
+        assert_eq!(
+            default_machine_id("uninitialized\n", &[]),
+            Some("uninitialized".to_string())
+        );
+        assert_eq!(
+            default_machine_id("d5e9a3c1b7f24e6a9c8b1a2d3e4f5a6b", &known),
+            None
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn machine_id_older_than_root() {
+        use crate::utils::fixture_dir;
+        use std::time::{Duration, SystemTime};
+
+        let root = fixture_dir(&[("etc/machine-id", "d5e9a3c1b7f24e6a9c8b1a2d3e4f5a6b\n")]);
+        if fs::metadata(&root).and_then(|m| m.created()).is_err() {
+            // The filesystem doesn't record birth times
+            return;
+        }
+        let machine_id = root.join("etc/machine-id");
+        assert!(!machine_id_predates_root(&machine_id, &root));
+
+        let copied = SystemTime::now() - Duration::from_secs(86400 * 365);
+        fs::File::options()
+            .write(true)
+            .open(&machine_id)
+            .unwrap()
+            .set_modified(copied)
+            .unwrap();
+        assert!(machine_id_predates_root(&machine_id, &root));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::errors::HWIDError;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+#[cfg(not(target_os = "windows"))]
+const SSH_CONFIG_DIR: &str = "/etc/ssh";
+#[cfg(target_os = "windows")]
+const SSH_CONFIG_DIR: &str = "C:\\ProgramData\\ssh";
+
+/// Host key types, from the most to the least preferred.
+const HOST_KEY_TYPES: [&str; 4] = ["ed25519", "ecdsa", "rsa", "dsa"];
+
+fn invalid_key() -> HWIDError {
+    HWIDError::new("SshKeyError", "Invalid OpenSSH public key")
+}
+
+/// Returns the SHA-256 fingerprint of an OpenSSH public key, as printed by `ssh-keygen -lf`.
+pub(crate) fn fingerprint(public_key: &str) -> Result<String, HWIDError> {
+    let mut fields = public_key.split_whitespace();
+    let key_type = fields.next().ok_or_else(invalid_key)?;
+    let blob = STANDARD
+        .decode(fields.next().ok_or_else(invalid_key)?)
+        .map_err(|_| invalid_key())?;
+
+    // The blob starts with the key type again, as a length-prefixed string
+    let type_len = blob
+        .get(0..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .ok_or_else(invalid_key)?;
+    if blob.get(4..4 + type_len) != Some(key_type.as_bytes()) {
+        return Err(invalid_key());
+    }
+
+    Ok(format!(
+        "SHA256:{}",
+        STANDARD_NO_PAD.encode(Sha256::digest(&blob))
+    ))
+}
+
+fn get_host_key_fingerprint_from(ssh_dir: &Path) -> Result<String, HWIDError> {
+    for key_type in HOST_KEY_TYPES.iter() {
+        let path = ssh_dir.join(format!("ssh_host_{key_type}_key.pub"));
+        if let Ok(public_key) = fs::read_to_string(path) {
+            return fingerprint(&public_key);
+        }
+    }
+    Err(HWIDError::new(
+        "SshKeyError",
+        "Could not find any SSH host key",
+    ))
+}
+
+pub(crate) fn get_host_key_fingerprint() -> Result<String, HWIDError> {
+    get_host_key_fingerprint_from(Path::new(SSH_CONFIG_DIR))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::fixture_dir;
+
+    const ED25519_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHRTPfhC3TeZxV79g6mW5HKoDnoqDy4QPOa7KfTp8aVL root@host\n";
+    const ED25519_FINGERPRINT: &str = "SHA256:khlHAwe/VZ3CDcAK6EJPrg4dH411rDj7GoeXoDmBk0I";
+    const RSA_KEY: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDFUqhJNIjsiLBIMQ58G3i+bRsRy2OhWPFDftpoRVNOllQ2Sa4J8sSH4P8eppDuqjP0ip1FHfdLLEvmLSbt/TMksa11yGcthmtpeDzHaGfII/3YzqS8d95fNuN1o2bcq6sUs3weYoP9qv9PspXwhIJsW9K9C3lqVNkuU5xM/l0+Qw== root@host\n";
+    const RSA_FINGERPRINT: &str = "SHA256:/nWONFKUsUxy1suxEdkd9bOpIuGYZ1i/3mwrBAo8IN0";
+
+    #[test]
+    fn fingerprint_matches_ssh_keygen() {
+        assert_eq!(fingerprint(ED25519_KEY).unwrap(), ED25519_FINGERPRINT);
+        assert_eq!(fingerprint(RSA_KEY).unwrap(), RSA_FINGERPRINT);
+    }
+
+    #[test]
+    fn reject_mismatched_key_type() {
+        assert!(fingerprint(&ED25519_KEY.replace("ssh-ed25519", "ssh-rsa")).is_err());
+        assert!(fingerprint("ssh-ed25519 not-base64!").is_err());
+        assert!(fingerprint("").is_err());
+    }
+
+    #[test]
+    fn host_key_preference_order() {
+        let root = fixture_dir(&[
+            ("ssh_host_rsa_key.pub", RSA_KEY),
+            ("ssh_host_ed25519_key.pub", ED25519_KEY),
+        ]);
+        assert_eq!(
+            get_host_key_fingerprint_from(&root).unwrap(),
+            ED25519_FINGERPRINT
+        );
+        fs::remove_file(root.join("ssh_host_ed25519_key.pub")).unwrap();
+        assert_eq!(
+            get_host_key_fingerprint_from(&root).unwrap(),
+            RSA_FINGERPRINT
+        );
+        fs::remove_file(root.join("ssh_host_rsa_key.pub")).unwrap();
+        assert!(get_host_key_fingerprint_from(&root).is_err());
+    }
+}
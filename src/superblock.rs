@@ -0,0 +1,155 @@
+use crate::errors::HWIDError;
+use std::io::{Read, Seek, SeekFrom};
+
+const EXT_SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT_MAGIC: [u8; 2] = [0x53, 0xEF];
+const XFS_MAGIC: &[u8] = b"XFSB";
+const BTRFS_SUPERBLOCK_OFFSET: u64 = 0x10000;
+const BTRFS_MAGIC: &[u8] = b"_BHRfS_M";
+const FAT_BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Reads `len` bytes at `offset`, or `None` if the device is too small.
+fn read_at<R: Read + Seek>(device: &mut R, offset: u64, len: usize) -> Option<Vec<u8>> {
+    let mut buffer = vec![0; len];
+    device.seek(SeekFrom::Start(offset)).ok()?;
+    device.read_exact(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+/// Formats 16 bytes the way blkid prints filesystem UUIDs.
+fn format_uuid(bytes: &[u8]) -> String {
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn read_ext_uuid<R: Read + Seek>(device: &mut R) -> Option<String> {
+    let superblock = read_at(device, EXT_SUPERBLOCK_OFFSET, 0x78)?;
+    if superblock[0x38..0x3A] != EXT_MAGIC {
+        return None;
+    }
+    Some(format_uuid(&superblock[0x68..0x78]))
+}
+
+fn read_xfs_uuid<R: Read + Seek>(device: &mut R) -> Option<String> {
+    let superblock = read_at(device, 0, 48)?;
+    if &superblock[0..4] != XFS_MAGIC {
+        return None;
+    }
+    Some(format_uuid(&superblock[32..48]))
+}
+
+fn read_btrfs_uuid<R: Read + Seek>(device: &mut R) -> Option<String> {
+    let superblock = read_at(device, BTRFS_SUPERBLOCK_OFFSET, 0x48)?;
+    if &superblock[0x40..0x48] != BTRFS_MAGIC {
+        return None;
+    }
+    Some(format_uuid(&superblock[0x20..0x30]))
+}
+
+fn read_vfat_volume_id<R: Read + Seek>(device: &mut R) -> Option<String> {
+    let boot_sector = read_at(device, 0, 512)?;
+    if boot_sector[0x1FE..0x200] != FAT_BOOT_SIGNATURE {
+        return None;
+    }
+    // FAT32 has a bigger BPB, which moves the extended boot record
+    let id_offset = if &boot_sector[0x52..0x57] == b"FAT32" {
+        0x43
+    } else if &boot_sector[0x36..0x39] == b"FAT" {
+        0x27
+    } else {
+        return None;
+    };
+    let id = u32::from_le_bytes([
+        boot_sector[id_offset],
+        boot_sector[id_offset + 1],
+        boot_sector[id_offset + 2],
+        boot_sector[id_offset + 3],
+    ]);
+    Some(format!("{:04X}-{:04X}", id >> 16, id & 0xFFFF))
+}
+
+/// Reads the UUID (or volume ID for vfat) from the superblock of an ext2/3/4, XFS, btrfs or vfat filesystem.
+pub(crate) fn read_filesystem_uuid<R: Read + Seek>(device: &mut R) -> Result<String, HWIDError> {
+    read_ext_uuid(device)
+        .or_else(|| read_xfs_uuid(device))
+        .or_else(|| read_btrfs_uuid(device))
+        .or_else(|| read_vfat_volume_id(device))
+        .ok_or(HWIDError::new(
+            "SuperblockError",
+            "Unknown or unsupported filesystem",
+        ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    const UUID: [u8; 16] = [
+        0x2f, 0x1c, 0x7a, 0x52, 0x5d, 0x1e, 0x4b, 0x8a, 0x9d, 0x2c, 0x6f, 0x1e, 0x3b, 0x0c, 0x9a,
+        0x11,
+    ];
+    const FORMATTED_UUID: &str = "2f1c7a52-5d1e-4b8a-9d2c-6f1e3b0c9a11";
+
+    fn image(size: usize, fields: &[(usize, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut image = vec![0; size];
+        for (offset, bytes) in fields {
+            image[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        Cursor::new(image)
+    }
+
+    #[test]
+    fn ext4_uuid() {
+        let mut device = image(4096, &[(1024 + 0x38, &EXT_MAGIC), (1024 + 0x68, &UUID)]);
+        assert_eq!(read_filesystem_uuid(&mut device).unwrap(), FORMATTED_UUID);
+    }
+
+    #[test]
+    fn xfs_uuid() {
+        let mut device = image(4096, &[(0, XFS_MAGIC), (32, &UUID)]);
+        assert_eq!(read_filesystem_uuid(&mut device).unwrap(), FORMATTED_UUID);
+    }
+
+    #[test]
+    fn btrfs_uuid() {
+        let mut device = image(0x11000, &[(0x10040, BTRFS_MAGIC), (0x10020, &UUID)]);
+        assert_eq!(read_filesystem_uuid(&mut device).unwrap(), FORMATTED_UUID);
+    }
+
+    #[test]
+    fn vfat_volume_id() {
+        let id = 0x1A2B3C4Du32.to_le_bytes();
+        let mut fat32 = image(
+            1024,
+            &[
+                (0x43, &id),
+                (0x52, b"FAT32   "),
+                (0x1FE, &FAT_BOOT_SIGNATURE),
+            ],
+        );
+        assert_eq!(read_filesystem_uuid(&mut fat32).unwrap(), "1A2B-3C4D");
+        let mut fat16 = image(
+            1024,
+            &[
+                (0x27, &id),
+                (0x36, b"FAT16   "),
+                (0x1FE, &FAT_BOOT_SIGNATURE),
+            ],
+        );
+        assert_eq!(read_filesystem_uuid(&mut fat16).unwrap(), "1A2B-3C4D");
+    }
+
+    #[test]
+    fn unknown_filesystem() {
+        assert!(read_filesystem_uuid(&mut image(512, &[])).is_err());
+        assert!(read_filesystem_uuid(&mut image(0, &[])).is_err());
+    }
+}
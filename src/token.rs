@@ -0,0 +1,381 @@
+use crate::errors::HWIDError;
+use crate::utils::{file_token_with, read_token, PRIVATE_TOKEN_MODE};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "token";
+
+/// Permissions of the system-wide tokens, which every user reads to get the same ID
+const SYSTEM_TOKEN_MODE: u32 = 0o644;
+
+/// First line of a token bound to the hardware, followed by the UUID and the hex HMAC
+const BOUND_TOKEN_HEADER: &str = "machineid-rs bound token v1";
+
+/// Where [`HWIDComponent::StoredToken`](crate::HWIDComponent::StoredToken) keeps its token.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum TokenLocation {
+    /// Per-user, in `$XDG_DATA_HOME/<app>/token` or `~/.local/share/<app>/token`,
+    /// `~/Library/Application Support/<app>/token` on macOS and `%LOCALAPPDATA%\<app>\token` on Windows
+    User(String),
+    /// System-wide, in `/var/lib/<app>/token`, `/Library/Application Support/<app>/token` on macOS
+    /// and `%PROGRAMDATA%\<app>\token` on Windows
+    ///
+    /// Falls back to the `User` location when there is no system-wide token and its directory is
+    /// not writable, i.e. for users other than the installer. It is created with permissions 0644
+    /// so that every user reads it, while per-user tokens are created with permissions 0600.
+    System(String),
+    /// A token file at the given path
+    Path(PathBuf),
+}
+
+/// What [`HWIDComponent::StoredToken`](crate::HWIDComponent::StoredToken) does when the token is missing.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum TokenMode {
+    /// Create a random token, as `HWIDComponent::FileToken` does
+    CreateIfMissing,
+    /// Fail, i.e. to notice that the token was deleted since the installer created it
+    MustExist,
+}
+
+fn token_error(message: &str) -> HWIDError {
+    HWIDError::new("TokenError", message)
+}
+
+/// Checks that the app name is a single directory name, so it can't point outside of the data directories.
+fn app_dir(app: &str) -> Result<&Path, HWIDError> {
+    let path = Path::new(app);
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(path),
+        _ => Err(token_error(&format!("Invalid app name {app:?}"))),
+    }
+}
+
+fn user_data_dir(env: &dyn Fn(&str) -> Option<String>) -> Result<PathBuf, HWIDError> {
+    let var = |name: &str| {
+        env(name)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+    };
+    let dir = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        // Relative paths are invalid per the XDG spec
+        var("XDG_DATA_HOME")
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| var("HOME").map(|home| home.join(".local/share")))
+    };
+    dir.ok_or(token_error("Could not find the user data directory"))
+}
+
+fn system_data_dir(env: &dyn Fn(&str) -> Option<String>) -> PathBuf {
+    if cfg!(windows) {
+        env("PROGRAMDATA")
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| "C:\\ProgramData".to_string())
+            .into()
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from("/Library/Application Support")
+    } else {
+        PathBuf::from("/var/lib")
+    }
+}
+
+/// The token files to look at, in order of preference, with the permissions to create them with.
+fn token_paths(
+    location: &TokenLocation,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<Vec<(PathBuf, u32)>, HWIDError> {
+    Ok(match location {
+        TokenLocation::User(app) => vec![(
+            user_data_dir(env)?
+                .join(app_dir(app)?)
+                .join(TOKEN_FILE_NAME),
+            PRIVATE_TOKEN_MODE,
+        )],
+        TokenLocation::System(app) => {
+            let mut paths = vec![(
+                system_data_dir(env)
+                    .join(app_dir(app)?)
+                    .join(TOKEN_FILE_NAME),
+                SYSTEM_TOKEN_MODE,
+            )];
+            if let Ok(dir) = user_data_dir(env) {
+                paths.push((dir.join(app).join(TOKEN_FILE_NAME), PRIVATE_TOKEN_MODE));
+            }
+            paths
+        }
+        TokenLocation::Path(path) => vec![(path.clone(), PRIVATE_TOKEN_MODE)],
+    })
+}
+
+/// Whether the token can be created at `path`, from the permissions of its closest existing directory.
+fn can_create(path: &Path) -> bool {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        let current = if current.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            current
+        };
+        match fs::metadata(current) {
+            Ok(metadata) if metadata.is_dir() => return is_writable(current, &metadata),
+            Ok(_) => return false,
+            Err(_) => dir = current.parent(),
+        }
+    }
+    false
+}
+
+#[cfg(unix)]
+fn is_writable(dir: &Path, _: &fs::Metadata) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        // access() also fails on read-only filesystems, even for root
+        Ok(dir) => unsafe { libc::access(dir.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_writable(_: &Path, metadata: &fs::Metadata) -> bool {
+    !metadata.permissions().readonly()
+}
+
+fn get_token_from(
+    paths: &[(PathBuf, u32)],
+    mode: &TokenMode,
+    new_token: &dyn Fn() -> Result<String, HWIDError>,
+) -> Result<(PathBuf, String), HWIDError> {
+    // An existing token wins, even in a fallback location, so the token doesn't change
+    // when the preferred location becomes writable later on. A token that can't be read
+    // is an error rather than missing, so that users don't each get their own token.
+    for (path, _) in paths {
+        if let Some(token) = read_token(path)? {
+            return Ok((path.clone(), token));
+        }
+    }
+    if *mode == TokenMode::MustExist {
+        let paths = paths
+            .iter()
+            .map(|(path, _)| path.display().to_string())
+            .collect::<Vec<String>>();
+        return Err(HWIDError::new(
+            "TokenMissing",
+            &format!("The token is missing from {}", paths.join(", ")),
+        ));
+    }
+    let (path, permissions) = paths
+        .iter()
+        .find(|(path, _)| can_create(path))
+        .ok_or(token_error("No writable location to store the token"))?;
+    Ok((
+        path.clone(),
+        file_token_with(path, *permissions, new_token)?,
+    ))
+}
+
+/// Returns the path and contents of the stored token, creating it with `new_token` if allowed.
+pub(crate) fn get_stored_token_with(
+    location: &TokenLocation,
+    mode: &TokenMode,
+    new_token: &dyn Fn() -> Result<String, HWIDError>,
+) -> Result<(PathBuf, String), HWIDError> {
+    let paths = token_paths(location, &|name| env::var(name).ok())?;
+    get_token_from(&paths, mode, new_token)
+}
+
+pub(crate) fn get_stored_token(
+    location: &TokenLocation,
+    mode: &TokenMode,
+) -> Result<String, HWIDError> {
+    let (_, token) = get_stored_token_with(location, mode, &|| Ok(Uuid::new_v4().to_string()))?;
+    token_uuid(&token)
+}
+
+fn token_mac(uuid: &str, key: &str, binding: &str) -> Result<Hmac<Sha256>, HWIDError> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())?;
+    mac.update(uuid.as_bytes());
+    mac.update(b"\n");
+    mac.update(binding.as_bytes());
+    Ok(mac)
+}
+
+/// Creates a random token bound to the `binding` components with an HMAC keyed by `key`.
+pub(crate) fn seal_token(key: &str, binding: &str) -> Result<String, HWIDError> {
+    let uuid = Uuid::new_v4().to_string();
+    let mac = token_mac(&uuid, key, binding)?.finalize().into_bytes();
+    Ok(format!(
+        "{BOUND_TOKEN_HEADER}\n{uuid}\n{}\n",
+        hex::encode(mac)
+    ))
+}
+
+/// Splits a token into its UUID and, for bound tokens, its HMAC.
+fn parse_token(content: &str) -> Result<(&str, Option<Vec<u8>>), HWIDError> {
+    let mut lines = content.lines();
+    if lines.next() != Some(BOUND_TOKEN_HEADER) {
+        return Ok((content, None));
+    }
+    match (lines.next(), lines.next().map(hex::decode)) {
+        (Some(uuid), Some(Ok(mac))) => Ok((uuid, Some(mac))),
+        _ => Err(token_error("Invalid bound token")),
+    }
+}
+
+/// Returns the UUID of a token without checking its binding, so that bound and plain tokens
+/// give the same ID whether or not bindings are configured.
+pub(crate) fn token_uuid(content: &str) -> Result<String, HWIDError> {
+    Ok(parse_token(content)?.0.to_string())
+}
+
+/// Returns the UUID of a token, and whether it was bound to other hardware than `binding`.
+///
+/// Plain tokens are returned as is, and never reported as moved.
+pub(crate) fn open_token(
+    content: &str,
+    key: &str,
+    binding: &str,
+) -> Result<(String, bool), HWIDError> {
+    let (uuid, mac) = match parse_token(content)? {
+        (uuid, Some(mac)) => (uuid, mac),
+        (uuid, None) => return Ok((uuid.to_string(), false)),
+    };
+    let moved = token_mac(uuid, key, binding)?.verify_slice(&mac).is_err();
+    Ok((uuid.to_string(), moved))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::fixture_dir;
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn user_and_system_paths() {
+        let env = |name: &str| match name {
+            "HOME" => Some("/home/alice".to_string()),
+            "XDG_DATA_HOME" => Some("relative/dir".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            token_paths(&TokenLocation::User("myapp".into()), &env).unwrap(),
+            vec![(PathBuf::from("/home/alice/.local/share/myapp/token"), 0o600)]
+        );
+        assert_eq!(
+            token_paths(&TokenLocation::System("myapp".into()), &env).unwrap(),
+            vec![
+                (PathBuf::from("/var/lib/myapp/token"), 0o644),
+                (PathBuf::from("/home/alice/.local/share/myapp/token"), 0o600),
+            ]
+        );
+        let env = |name: &str| (name == "XDG_DATA_HOME").then(|| "/data".to_string());
+        assert_eq!(
+            token_paths(&TokenLocation::User("myapp".into()), &env).unwrap(),
+            vec![(PathBuf::from("/data/myapp/token"), 0o600)]
+        );
+    }
+
+    #[test]
+    fn reject_app_paths() {
+        let env = |_: &str| Some("/home/alice".to_string());
+        for app in ["", "..", "../etc", "a/b", "/etc"] {
+            assert!(token_paths(&TokenLocation::User(app.into()), &env).is_err());
+        }
+    }
+
+    #[test]
+    fn fallback_when_not_writable() {
+        // The system location can't be created, as its parent is a file
+        let root = fixture_dir(&[("system", "not a directory")]);
+        let paths = vec![
+            (root.join("system/myapp/token"), SYSTEM_TOKEN_MODE),
+            (root.join("user/myapp/token"), PRIVATE_TOKEN_MODE),
+        ];
+        let new_token = || Ok("0f5e7a1c-3b2d-4e6f-8a9b-1c2d3e4f5a6b".to_string());
+        let (path, token) =
+            get_token_from(&paths, &TokenMode::CreateIfMissing, &new_token).unwrap();
+        assert_eq!(path, paths[1].0);
+        assert_eq!(token, "0f5e7a1c-3b2d-4e6f-8a9b-1c2d3e4f5a6b");
+        assert_eq!(
+            get_token_from(&paths, &TokenMode::MustExist, &new_token).unwrap(),
+            (paths[1].0.clone(), token)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn system_token_readable_by_all() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = fixture_dir::<&str>(&[]);
+        let paths = vec![
+            (root.join("system/myapp/token"), SYSTEM_TOKEN_MODE),
+            (root.join("user/myapp/token"), PRIVATE_TOKEN_MODE),
+        ];
+        let new_token = || Ok(Uuid::new_v4().to_string());
+        let (path, _) = get_token_from(&paths, &TokenMode::CreateIfMissing, &new_token).unwrap();
+        assert_eq!(path, paths[0].0);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o644);
+    }
+
+    #[test]
+    fn unreadable_system_token() {
+        // Tests may run as root, which reads any file, so the token is a directory instead
+        let root = fixture_dir(&[("system/myapp/token/entry", "")]);
+        let paths = vec![
+            (root.join("system/myapp/token"), SYSTEM_TOKEN_MODE),
+            (root.join("user/myapp/token"), PRIVATE_TOKEN_MODE),
+        ];
+        let new_token = || Ok(Uuid::new_v4().to_string());
+        for mode in [TokenMode::CreateIfMissing, TokenMode::MustExist] {
+            let error = get_token_from(&paths, &mode, &new_token).unwrap_err();
+            assert!(!error.to_string().starts_with("TokenMissing"));
+        }
+        assert!(!paths[1].0.exists());
+    }
+
+    #[test]
+    fn missing_token() {
+        let root = fixture_dir(&[("user/myapp/token", "")]);
+        let paths = vec![(root.join("user/myapp/token"), PRIVATE_TOKEN_MODE)];
+        let new_token = || Ok(Uuid::new_v4().to_string());
+        assert!(get_token_from(&paths, &TokenMode::MustExist, &new_token).is_err());
+        assert_eq!(read_token(&paths[0].0).unwrap(), None);
+    }
+
+    #[test]
+    fn bound_token() {
+        let token = seal_token("mykey", "x86_64ubuntu").unwrap();
+        let (uuid, moved) = open_token(&token, "mykey", "x86_64ubuntu").unwrap();
+        assert!(Uuid::parse_str(&uuid).is_ok());
+        assert!(!moved);
+        assert_eq!(
+            open_token(&token, "mykey", "aarch64ubuntu").unwrap(),
+            (uuid.clone(), true)
+        );
+        assert_eq!(
+            open_token(&token, "otherkey", "x86_64ubuntu").unwrap(),
+            (uuid.clone(), true)
+        );
+
+        let plain = "0f5e7a1c-3b2d-4e6f-8a9b-1c2d3e4f5a6b";
+        assert_eq!(
+            open_token(plain, "mykey", "x86_64ubuntu").unwrap(),
+            (plain.to_string(), false)
+        );
+        let truncated = format!("{BOUND_TOKEN_HEADER}\n{plain}\n");
+        assert!(open_token(&truncated, "mykey", "x86_64ubuntu").is_err());
+        assert!(token_uuid(&truncated).is_err());
+        assert_eq!(token_uuid(&token).unwrap(), uuid);
+        assert_eq!(token_uuid(plain).unwrap(), plain);
+    }
+}
@@ -1,20 +1,282 @@
 use crate::errors::HWIDError;
-use std::fs::OpenOptions;
-use std::io::{Read, Write};
+use crate::filehash::hash_file;
+#[cfg(unix)]
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
 use uuid::Uuid;
 
-pub(crate) fn file_token(path: &str) -> Result<String, HWIDError> {
-    let mut file = OpenOptions::new()
+/// Permissions of the token files only their owner reads, on Unix
+pub(crate) const PRIVATE_TOKEN_MODE: u32 = 0o600;
+
+/// Tokens bigger than this are hashed instead of being read in memory
+const MAX_TOKEN_SIZE: u64 = 64 * 1024;
+
+/// How long to wait for another process holding the token lock on Windows
+#[cfg(windows)]
+const LOCK_ATTEMPTS: u32 = 500;
+
+/// Reads a token, an empty file or a path going through a file counting as a missing one.
+///
+/// Tokens that are not text or bigger than `MAX_TOKEN_SIZE` are replaced by their SHA-256.
+pub(crate) fn read_token(path: &Path) -> Result<Option<String>, HWIDError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut content = vec![];
+    file.take(MAX_TOKEN_SIZE + 1).read_to_end(&mut content)?;
+    if content.is_empty() {
+        return Ok(None);
+    }
+    if content.len() as u64 <= MAX_TOKEN_SIZE {
+        if let Ok(token) = String::from_utf8(content) {
+            return Ok(Some(token));
+        }
+    }
+    Ok(Some(hash_file(path)?))
+}
+
+/// Takes an exclusive advisory lock on `path`, released when the returned file is closed.
+#[cfg(unix)]
+fn lock(path: &Path) -> Result<File, HWIDError> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new()
         .write(true)
-        .read(true)
         .create(true)
+        .truncate(false)
+        .mode(0o600)
         .open(path)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-    if content.is_empty() {
-        let uuid = Uuid::new_v4().to_string();
-        file.write_all(uuid.as_bytes()).unwrap();
-        content = uuid.to_string();
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(file)
+}
+
+/// Opens `path` without sharing it, which other processes wait for until the returned file is closed.
+#[cfg(windows)]
+fn lock(path: &Path) -> Result<File, HWIDError> {
+    use std::os::windows::fs::OpenOptionsExt;
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+
+    for _ in 0..LOCK_ATTEMPTS {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .share_mode(0)
+            .open(path);
+        match file {
+            Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                std::thread::sleep(std::time::Duration::from_millis(10))
+            }
+            file => return Ok(file?),
+        }
+    }
+    Err(HWIDError::new(
+        "FileTokenError",
+        &format!("Timed out waiting for the lock {}", path.display()),
+    ))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock(path: &Path) -> Result<File, HWIDError> {
+    Ok(OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?)
+}
+
+/// Writes `content` to a new file next to `path` with permissions `mode` on Unix, then renames it over `path`.
+fn write_atomically(path: &Path, content: &[u8], mode: u32) -> Result<(), HWIDError> {
+    let name = path.file_name().ok_or(HWIDError::new(
+        "FileTokenError",
+        &format!("{} is not a file path", path.display()),
+    ))?;
+    let temp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        Uuid::new_v4()
+    ));
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    let written = options.open(&temp).and_then(|mut file| {
+        // The umask may have taken permissions away
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        file.write_all(content)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&temp, path)) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    // Makes the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
+/// Returns the token stored at `path`, creating a random one if there is none.
+pub(crate) fn file_token<P: AsRef<Path>>(path: P) -> Result<String, HWIDError> {
+    file_token_with(path.as_ref(), PRIVATE_TOKEN_MODE, &|| {
+        Ok(Uuid::new_v4().to_string())
+    })
+}
+
+/// Returns the token stored at `path`, creating it with `new_token` if there is none.
+///
+/// The token is written to a temporary file that is renamed once complete, with permissions `mode` on Unix,
+/// while holding a lock on `<path>.lock` so that processes starting together agree on the token.
+pub(crate) fn file_token_with(
+    path: &Path,
+    mode: u32,
+    new_token: &dyn Fn() -> Result<String, HWIDError>,
+) -> Result<String, HWIDError> {
+    if let Some(token) = read_token(path)? {
+        return Ok(token);
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let _lock = lock(Path::new(&lock_path))?;
+    // Another process may have created the token while we waited for the lock
+    if let Some(token) = read_token(path)? {
+        return Ok(token);
+    }
+    let token = new_token()?;
+    write_atomically(path, token.as_bytes(), mode)?;
+    Ok(token)
+}
+
+/// Architecture of the running kernel, i.e. `x86_64` even for a 32 bits build.
+#[cfg(unix)]
+pub(crate) fn architecture() -> Result<String, HWIDError> {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let machine = unsafe { CStr::from_ptr(name.machine.as_ptr()) };
+    Ok(machine.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn architecture() -> Result<String, HWIDError> {
+    Ok(std::env::consts::ARCH.to_string())
+}
+
+/// A temporary directory of test files, removed when dropped.
+#[cfg(test)]
+pub(crate) struct FixtureDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl std::ops::Deref for FixtureDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for FixtureDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for FixtureDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn fixture_dir<S: AsRef<str>>(files: &[(S, S)]) -> FixtureDir {
+    let root = std::env::temp_dir().join(format!("machineid-rs-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&root).unwrap();
+    for (path, content) in files {
+        let path = root.join(path.as_ref());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content.as_ref()).unwrap();
+    }
+    FixtureDir(root)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn create_token() {
+        let root = fixture_dir::<&str>(&[]);
+        let path = root.join("app/data/token");
+        let token = file_token(&path).unwrap();
+        assert!(Uuid::parse_str(&token).is_ok());
+        assert_eq!(file_token(&path).unwrap(), token);
+        assert_eq!(fs::read_to_string(&path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn replace_empty_token() {
+        let root = fixture_dir(&[("token", ""), ("existing", "my-token")]);
+        assert!(!file_token(root.join("token")).unwrap().is_empty());
+        assert_eq!(file_token(root.join("existing")).unwrap(), "my-token");
+    }
+
+    #[test]
+    fn binary_token() {
+        let root = fixture_dir::<&str>(&[]);
+        let binary = root.join("license.bin");
+        fs::write(&binary, [0xff, 0xfe, 0x00, 0x80]).unwrap();
+        let big = root.join("bundle.txt");
+        fs::write(&big, "a".repeat(MAX_TOKEN_SIZE as usize + 1)).unwrap();
+        for path in [binary, big] {
+            assert_eq!(file_token(&path).unwrap(), hash_file(&path).unwrap());
+        }
+    }
+
+    #[test]
+    fn concurrent_token_creation() {
+        let root = fixture_dir::<&str>(&[]);
+        let path = root.join("token");
+        let tokens = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || file_token(path).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect::<Vec<String>>();
+        assert!(tokens.iter().all(|token| *token == tokens[0]));
     }
-    return Ok(content);
 }